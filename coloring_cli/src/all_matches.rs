@@ -0,0 +1,53 @@
+use crate::nesting::{nested_content, Nesting};
+use crate::{outline, print_content, Format, Mode};
+use anyhow::Result;
+use coloring_common::{matcher_name, Edition, WholeMatcher};
+use console::Style;
+
+/// Shows the result of each of `hit_matchers` one after another, the top one first.
+pub fn all_matches_mode(
+    content: &str,
+    hit_matchers: &[WholeMatcher],
+    edition: Edition,
+    format: Format,
+    nesting: Nesting,
+    mode: Mode,
+) -> Result<()> {
+    let header_style = Style::new().bold();
+
+    for (i, matcher) in hit_matchers.iter().enumerate() {
+        let fragments = matcher.try_parse(content, edition)?;
+
+        if i > 0 {
+            println!();
+        }
+        let top = if i == 0 { " ( top )" } else { "" };
+        println!(
+            "{}",
+            header_style.apply_to(format!("Match: {}{}", matcher_name(*matcher), top))
+        );
+
+        let res = match format {
+            Format::Tree => {
+                let tree = fragments
+                    .tree
+                    .into_iter()
+                    .flat_map(|node| node.filter(&|frag| mode.is_target(frag)))
+                    .collect::<Vec<_>>();
+
+                outline::outline(content, &tree)
+            }
+            _ => nested_content(
+                content,
+                fragments.byte_ranges(),
+                fragments.tree,
+                nesting,
+                &mode,
+            ),
+        };
+
+        print_content(&res);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,428 @@
+use crate::nesting::{self, Nesting};
+use crate::{html, json, outline, stats, Format, Mode};
+use anyhow::{Context, Result};
+use clap::Args;
+use coloring_common::{classify, matcher_name, Edition, Stats, WholeMatcher};
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+
+#[derive(Args)]
+pub struct Batch {
+    /// Classify every `.rs` file in these files and directories, and write the results
+    /// to `--out-dir` in `--format`
+    #[arg(
+        long = "batch",
+        id = "batch",
+        value_name = "PATHS",
+        num_args = 1..,
+        conflicts_with_all = [
+            "content", "file_path", "macro_rules", "range", "lines", "all_matches", "legend", "stats",
+            "why_not", "recover"
+        ]
+    )]
+    pub paths: Option<Vec<PathBuf>>,
+
+    /// Directory the results of `--batch` are written to. Its summary goes to `summary.txt`
+    #[arg(long, requires = "batch", conflicts_with_all = ["content", "file_path"])]
+    pub out_dir: Option<PathBuf>,
+
+    /// Classify each top-level item of the files instead of the whole files.
+    /// `item` is tried first unless `--matchers` is given
+    #[arg(long, requires = "batch", conflicts_with_all = ["content", "file_path"])]
+    pub per_item: bool,
+}
+
+/// A file or an item to classify.
+struct Unit {
+    /// `path` or `path:line` .
+    origin: String,
+    content: String,
+    /// Relative to `--out-dir` , without the extension.
+    out: PathBuf,
+}
+
+/// Writes the results of every `.rs` file under `paths` , and prints the summary.
+pub fn batch_mode(
+    Batch {
+        paths,
+        out_dir,
+        per_item,
+    }: Batch,
+    matchers: Option<Vec<WholeMatcher>>,
+    edition: Edition,
+    format: Format,
+    standalone: bool,
+    nesting: Nesting,
+    mode: Mode,
+) -> Result<()> {
+    let out_dir = out_dir.context("`--batch` needs `--out-dir`")?;
+
+    let matchers = matchers.unwrap_or_else(|| {
+        let mut matchers = WholeMatcher::DEFAULT_ORDER.to_vec();
+
+        // an item is reported as `item` rather than as a `file` of one item
+        if per_item {
+            matchers.retain(|m| *m != WholeMatcher::Item);
+            matchers.insert(0, WholeMatcher::Item);
+        }
+
+        matchers
+    });
+
+    let files = collect_paths(&paths.unwrap_or_default())?;
+
+    let extension = match format {
+        Format::Ansi => "ansi",
+        Format::Json => "json",
+        Format::Html => "html",
+        Format::Tree => "txt",
+    };
+
+    let mut summary = Summary::default();
+
+    for (path, relative) in files {
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let units = if per_item {
+            split_items(&path, &relative, &content)
+        } else {
+            vec![Unit {
+                origin: path.display().to_string(),
+                content,
+                out: relative.with_extension(""),
+            }]
+        };
+
+        for Unit {
+            origin,
+            content,
+            out,
+        } in units
+        {
+            let classification = classify(&content, &matchers, edition);
+
+            let Some(top_match) = &classification.top_match else {
+                eprintln!("{}: no matcher accepts the whole content", origin);
+                summary.failed += 1;
+
+                // the errors are a part of the result
+                if let Format::Json = format {
+                    let output = json::JsonOutput::new(&content, classification, edition, &mode);
+                    write(
+                        &out_dir,
+                        &out,
+                        extension,
+                        &serde_json::to_string_pretty(&output)?,
+                    )?;
+                }
+
+                continue;
+            };
+
+            let mut range_info = top_match.byte_ranges();
+            range_info.retain(|frag, _| mode.is_target(*frag));
+
+            let tree = top_match
+                .tree
+                .iter()
+                .cloned()
+                .flat_map(|node| node.filter(&|frag| mode.is_target(frag)))
+                .collect::<Vec<_>>();
+
+            summary.add(
+                top_match.matcher,
+                coloring_common::stats(content.len(), &tree),
+            );
+
+            let text = match format {
+                Format::Json => {
+                    let output = json::JsonOutput::new(&content, classification, edition, &mode);
+                    serde_json::to_string_pretty(&output)?
+                }
+                Format::Html => html::html_content(&content, range_info, standalone),
+                Format::Tree => outline::outline(&content, &tree),
+                Format::Ansi => nesting::nested_content(&content, range_info, tree, nesting, &mode),
+            };
+
+            write(&out_dir, &out, extension, &text)?;
+        }
+    }
+
+    let summary = summary.to_string();
+    print!("{}", summary);
+    std::fs::write(out_dir.join("summary.txt"), summary)
+        .with_context(|| format!("failed to write the summary to {}", out_dir.display()))?;
+
+    Ok(())
+}
+
+/// `.rs` files under every one of `paths` , with their paths relative to `--out-dir` .
+fn collect_paths(paths: &[PathBuf]) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let start = files.len();
+        collect_rs_files(path, path, &mut files)?;
+
+        // keeps the results of `a/lib.rs` and `b/lib.rs` apart
+        if let (true, Some(name)) = (paths.len() > 1, path.file_name()) {
+            for (_, relative) in &mut files[start..] {
+                *relative = Path::new(name).join(&*relative);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// `.rs` files under `path` , sorted, with their paths relative to `root` .
+/// Hidden directories and `target` are skipped.
+fn collect_rs_files(root: &Path, path: &Path, files: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    if path.is_file() {
+        let relative = match path.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+            _ => PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+        };
+        files.push((path.to_path_buf(), relative));
+
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(path)
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+
+        if entry.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                collect_rs_files(root, &entry, files)?;
+            }
+        } else if name.ends_with(".rs") {
+            collect_rs_files(root, &entry, files)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Top-level items of `content` , with their attributes and doc comments.
+/// The whole file is the only unit if it is not parsed as a file.
+fn split_items(path: &Path, relative: &Path, content: &str) -> Vec<Unit> {
+    let whole = || Unit {
+        origin: path.display().to_string(),
+        content: content.to_string(),
+        out: relative.with_extension(""),
+    };
+
+    let Ok(file) = syn::parse_file(content) else {
+        return vec![whole()];
+    };
+
+    file.items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let span = item.span();
+
+            Unit {
+                origin: format!("{}:{}", path.display(), span.start().line),
+                content: content[span.byte_range()].to_string(),
+                out: relative
+                    .with_extension("")
+                    .join(format!("item_{:03}", i + 1)),
+            }
+        })
+        .collect()
+}
+
+fn write(out_dir: &Path, out: &Path, extension: &str, text: &str) -> Result<()> {
+    let path = out_dir.join(out).with_extension(extension);
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+
+    std::fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[derive(Default)]
+struct Summary {
+    matched: Vec<(WholeMatcher, usize)>,
+    failed: usize,
+    stats: Stats,
+}
+
+impl Summary {
+    fn add(&mut self, matcher: WholeMatcher, stats: Stats) {
+        match self.matched.iter_mut().find(|(m, _)| *m == matcher) {
+            Some((_, count)) => *count += 1,
+            None => self.matched.push((matcher, 1)),
+        }
+
+        self.stats.merge(stats);
+    }
+}
+
+/// ```text
+/// Units: 12 ( failed: 1 )
+/// Whole Match: file 11
+///
+/// fragment     count    bytes  coverage  depth  kinds
+/// item            40     5123     92.4%      2  ItemFn 30, ItemUse 6, ItemStruct 4
+/// ```
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let matched = self.matched.iter().map(|(_, count)| count).sum::<usize>();
+        writeln!(
+            f,
+            "Units: {} ( failed: {} )",
+            matched + self.failed,
+            self.failed
+        )?;
+
+        let mut matched = self.matched.clone();
+        matched.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let matched = matched
+            .iter()
+            .map(|(matcher, count)| format!("{} {}", matcher_name(*matcher), count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(f, "Whole Match: {}", matched)?;
+        writeln!(f)?;
+
+        write!(f, "{}", stats::table(&self.stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the temporary directory with `files` in it.
+    fn fixture(name: &str, files: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "coloring_cli_batch_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&root);
+
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "fn f() {}\n").unwrap();
+        }
+
+        root
+    }
+
+    fn relatives(files: &[(PathBuf, PathBuf)]) -> Vec<PathBuf> {
+        files.iter().map(|(_, relative)| relative.clone()).collect()
+    }
+
+    #[test]
+    fn hidden_dirs_and_target_are_skipped() {
+        let root = fixture(
+            "skip",
+            &[
+                "src/main.rs",
+                "src/a/b.rs",
+                "build.rs",
+                "README.md",
+                ".git/hooks.rs",
+                "target/debug/build.rs",
+            ],
+        );
+
+        let mut files = Vec::new();
+        collect_rs_files(&root, &root, &mut files).unwrap();
+
+        assert_eq!(
+            relatives(&files),
+            [
+                PathBuf::from("build.rs"),
+                PathBuf::from("src/a/b.rs"),
+                PathBuf::from("src/main.rs"),
+            ]
+        );
+        assert!(files.iter().all(|(path, _)| path.starts_with(&root)));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn single_file_is_named_by_itself() {
+        let root = fixture("single", &["src/lib.rs"]);
+
+        let files = collect_paths(&[root.join("src/lib.rs")]).unwrap();
+        assert_eq!(relatives(&files), [PathBuf::from("lib.rs")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn several_paths_are_prefixed() {
+        let root = fixture("prefix", &["a/lib.rs", "b/lib.rs"]);
+
+        let files = collect_paths(&[root.join("a")]).unwrap();
+        assert_eq!(relatives(&files), [PathBuf::from("lib.rs")]);
+
+        let files = collect_paths(&[root.join("a"), root.join("b")]).unwrap();
+        assert_eq!(
+            relatives(&files),
+            [PathBuf::from("a/lib.rs"), PathBuf::from("b/lib.rs")]
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn items_are_split_with_their_attributes() {
+        let content = "use std::fs;\n\n/// doc\n#[derive(Debug)]\nstruct S;\n\nfn f() {}\n";
+        let units = split_items(Path::new("src/lib.rs"), Path::new("lib.rs"), content);
+
+        let units = units
+            .iter()
+            .map(
+                |Unit {
+                     origin,
+                     content,
+                     out,
+                 }| (origin.as_str(), content.as_str(), out.clone()),
+            )
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            units,
+            [
+                (
+                    "src/lib.rs:1",
+                    "use std::fs;",
+                    PathBuf::from("lib/item_001")
+                ),
+                (
+                    "src/lib.rs:3",
+                    "/// doc\n#[derive(Debug)]\nstruct S;",
+                    PathBuf::from("lib/item_002")
+                ),
+                ("src/lib.rs:7", "fn f() {}", PathBuf::from("lib/item_003")),
+            ]
+        );
+    }
+
+    #[test]
+    fn unparsable_file_is_one_unit() {
+        let content = "fn f( {}";
+        let units = split_items(Path::new("src/lib.rs"), Path::new("lib.rs"), content);
+
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].origin, "src/lib.rs");
+        assert_eq!(units[0].content, content);
+        assert_eq!(units[0].out, PathBuf::from("lib"));
+    }
+}
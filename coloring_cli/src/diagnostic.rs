@@ -0,0 +1,215 @@
+use coloring_common::{matcher_name, Diagnostic, WholeMatcher, WhyNot};
+use console::{measure_text_width, Style};
+use std::ops::Range;
+
+/// Spaces a tab is shown as.
+const TAB_WIDTH: usize = 4;
+
+/// Renders diagnostics like rustc does. The same message at the same place is shown once,
+/// labelled with every matcher which reported it and followed by their explanations.
+///
+/// ```text
+/// error: unexpected end of input, expected an expression
+///  --> main.rs:1:4
+///   |
+/// 1 | 1 +
+///   |    ^ stmt, expr
+///   = note: `stmt` needs more tokens after the content: ...
+///   = note: `expr` needs more tokens after the content: ...
+/// ```
+pub fn render(
+    content: &str,
+    origin: &str,
+    diagnostics: &[Diagnostic],
+    why_not: &[WhyNot],
+) -> String {
+    let mut groups: Vec<(&Diagnostic, Vec<WholeMatcher>)> = Vec::new();
+
+    for diagnostic in diagnostics {
+        let same = groups.iter_mut().find(|(d, _)| {
+            d.message == diagnostic.message && d.range.byte == diagnostic.range.byte
+        });
+
+        match same {
+            Some((_, matchers)) => matchers.push(diagnostic.matcher),
+            None => groups.push((diagnostic, vec![diagnostic.matcher])),
+        }
+    }
+
+    let lines = content.lines().collect::<Vec<_>>();
+    let gutter_width = lines.len().max(1).to_string().len();
+
+    groups
+        .into_iter()
+        .map(|(diagnostic, matchers)| {
+            let notes = why_not
+                .iter()
+                .filter(|w| matchers.contains(&w.matcher))
+                .map(|w| w.explanation.as_str())
+                .collect::<Vec<_>>();
+
+            render_one(&lines, gutter_width, origin, diagnostic, &matchers, &notes)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_one(
+    lines: &[&str],
+    gutter_width: usize,
+    origin: &str,
+    Diagnostic { message, range, .. }: &Diagnostic,
+    matchers: &[WholeMatcher],
+    notes: &[&str],
+) -> String {
+    let error_style = Style::new().for_stderr().red().bold();
+    let gutter_style = Style::new().for_stderr().blue().bold();
+    let message_style = Style::new().for_stderr().bold();
+
+    let line = range.start.line;
+    let source = lines
+        .get(line.saturating_sub(1))
+        .copied()
+        .unwrap_or_default();
+
+    let underline = underline(
+        source,
+        range.start.column..range.end.column,
+        range.end.line > line,
+    );
+    let source = source.replace('\t', &" ".repeat(TAB_WIDTH));
+
+    let mut label = matchers
+        .iter()
+        .map(|m| matcher_name(*m))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if range.end.line > line {
+        label.push_str(&format!(
+            " ( until {}:{} )",
+            range.end.line, range.end.column
+        ));
+    }
+
+    let pad = " ".repeat(gutter_width);
+    let bar = gutter_style.apply_to("|");
+
+    let notes = notes
+        .iter()
+        .enumerate()
+        .filter(|(i, note)| !notes[..*i].contains(note))
+        .map(|(_, note)| {
+            format!(
+                "{} {} {}: {}\n",
+                pad,
+                gutter_style.apply_to("="),
+                message_style.apply_to("note"),
+                note
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "{}{}\n{}{} {}:{}:{}\n{} {}\n{} {} {}\n{} {} {}{}\n{}",
+        error_style.apply_to("error"),
+        message_style.apply_to(format!(": {}", message)),
+        pad,
+        gutter_style.apply_to("-->"),
+        origin,
+        line,
+        range.start.column + 1,
+        pad,
+        bar,
+        gutter_style.apply_to(format!("{:>gutter_width$}", line)),
+        bar,
+        source,
+        pad,
+        bar,
+        underline,
+        error_style.apply_to(label),
+        notes,
+    )
+}
+
+/// Spaces up to `columns.start` and carets under the span, followed by a space.
+/// An empty span gets one caret, and a span going beyond the line is underlined to its end.
+/// `columns` are in chars of `source` , whose tabs are shown as `TAB_WIDTH` spaces.
+fn underline(source: &str, columns: Range<usize>, multiline: bool) -> String {
+    let width_until = |column: usize| source.chars().take(column).map(char_width).sum::<usize>();
+
+    let start = width_until(columns.start);
+    let end = if multiline {
+        width_until(usize::MAX)
+    } else {
+        width_until(columns.end)
+    };
+
+    let carets = Style::new()
+        .for_stderr()
+        .red()
+        .bold()
+        .apply_to("^".repeat((end.saturating_sub(start)).max(1)));
+
+    format!("{}{} ", " ".repeat(start), carets)
+}
+
+fn char_width(c: char) -> usize {
+    match c {
+        '\t' => TAB_WIDTH,
+        _ => measure_text_width(c.encode_utf8(&mut [0; 4])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coloring_common::{classify, diagnostics, Edition};
+    use console::strip_ansi_codes;
+
+    fn rendered(content: &str, matchers: &[WholeMatcher]) -> String {
+        let errors = classify(content, matchers, Edition::E2021).errors;
+
+        strip_ansi_codes(&render(
+            content,
+            "main.rs",
+            &diagnostics(content, &errors),
+            &[],
+        ))
+        .to_string()
+    }
+
+    #[test]
+    fn caret_under_the_error() {
+        let rendered = rendered("1 +", &[WholeMatcher::Stmt, WholeMatcher::Expr]);
+
+        assert_eq!(
+            rendered,
+            "error: unexpected end of input, expected an expression\n \
+             --> main.rs:1:4\n  \
+             |\n\
+             1 | 1 +\n  \
+             |    ^ stmt, expr\n"
+        );
+    }
+
+    #[test]
+    fn caret_after_tabs() {
+        let rendered = rendered("\tlet x = 1 +", &[WholeMatcher::Stmt]);
+        let lines = rendered.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[3], "1 |     let x = 1 +");
+        assert_eq!(lines[4], "  |                ^ stmt");
+    }
+
+    #[test]
+    fn underline_widths() {
+        let carets = |source, columns, multiline| {
+            strip_ansi_codes(&underline(source, columns, multiline)).to_string()
+        };
+
+        assert_eq!(carets("a\tb", 2..3, false), "     ^ ");
+        assert_eq!(carets("abc", 1..1, false), " ^ ");
+        assert_eq!(carets("日本 x", 1..2, false), "  ^^ ");
+        assert_eq!(carets("abc", 1..2, true), " ^^ ");
+    }
+}
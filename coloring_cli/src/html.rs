@@ -0,0 +1,107 @@
+use crate::theme::{ansi256_to_rgb, theme, BgFg, CustomStyle, ThemeColor};
+use coloring_common::{recovered_to_html_string, to_html_string, RangeMap, Recovered};
+use console::Color;
+
+pub fn html_content(content: &str, range_info: RangeMap, standalone: bool) -> String {
+    wrap(&to_html_string(content, range_info), standalone)
+}
+
+pub fn recovered_html_content(content: &str, recovered: &Recovered, standalone: bool) -> String {
+    wrap(&recovered_to_html_string(content, recovered), standalone)
+}
+
+fn wrap(code: &str, standalone: bool) -> String {
+    let code = format!("<pre><code>{}</code></pre>", code);
+
+    if !standalone {
+        return code;
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<style>
+{}
+</style>
+</head>
+<body>
+{}
+</body>
+</html>",
+        stylesheet(),
+        code
+    )
+}
+
+/// Same as the web version, each fragment gets its own cascade layer
+/// so that the fragment with the higher priority wins where spans are nested.
+fn stylesheet() -> String {
+    let layers = theme()
+        .priority()
+        .iter()
+        .map(|frag| frag.to_string().to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let rules = theme().priority().iter().map(|frag| {
+        let name = frag.to_string().to_ascii_lowercase();
+        let CustomStyle {
+            color,
+            bgfg,
+            bold,
+            underline,
+            italic,
+        } = theme().style(*frag);
+        let mut css = match bgfg {
+            BgFg::Bg => format!("background-color: {}; color: black;", css_color(color)),
+            BgFg::Fg => format!(
+                "background-color: transparent; color: {};",
+                css_color(color)
+            ),
+        };
+
+        if bold {
+            css.push_str(" font-weight: bold;");
+        }
+        if underline {
+            css.push_str(" text-decoration: underline;");
+        }
+        if italic {
+            css.push_str(" font-style: italic;");
+        }
+
+        format!("@layer {} {{ *.{} {{ {} }} }}", name, name, css)
+    });
+
+    std::iter::once(format!("@layer {};", layers))
+        .chain(rules)
+        .chain([
+            ".parse-error { text-decoration: red wavy underline; }".to_string(),
+            ".parse-error-message { color: red; font-style: italic; margin-left: 1ch; }"
+                .to_string(),
+        ])
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn css_color(color: ThemeColor) -> String {
+    let name = match color {
+        ThemeColor::Ansi(Color::Black) => "black",
+        ThemeColor::Ansi(Color::Red) => "red",
+        ThemeColor::Ansi(Color::Green) => "green",
+        ThemeColor::Ansi(Color::Yellow) => "gold",
+        ThemeColor::Ansi(Color::Blue) => "royalblue",
+        ThemeColor::Ansi(Color::Magenta) => "magenta",
+        ThemeColor::Ansi(Color::Cyan) => "darkcyan",
+        ThemeColor::Ansi(Color::White) => "white",
+        ThemeColor::Ansi(Color::Color256(n)) | ThemeColor::Ansi256(n) => {
+            let (r, g, b) = ansi256_to_rgb(n);
+            return format!("#{:02x}{:02x}{:02x}", r, g, b);
+        }
+        ThemeColor::Rgb(r, g, b) => return format!("#{:02x}{:02x}{:02x}", r, g, b),
+    };
+
+    name.to_string()
+}
@@ -0,0 +1,211 @@
+//! Schema of `--format json` .
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "edition": "2021",
+//!   "whole_match": "expr",
+//!   "hit_matchers": ["stmt", "expr"],
+//!   "errors": [
+//!     { "matcher": "file", "message": "...", "start": { "byte": 0, "line": 1, "column": 0, "utf16": 0 }, "end": ..., "explanation": "..." }
+//!   ],
+//!   "fragments": {
+//!     "expr": [{ "start": { "byte": 0, "line": 1, "column": 0, "utf16": 0 }, "end": { "byte": 5, "line": 1, "column": 5, "utf16": 5 } }]
+//!   },
+//!   "tree": [
+//!     { "frags": ["expr_2021", "expr"], "kind": "ExprCall", "start": ..., "end": ..., "children": [...] }
+//!   ]
+//! }
+//! ```
+//!
+//! - `whole_match` is `null` when no matcher accepts the whole content.
+//! - `hit_matchers` lists every matcher accepting the whole content in the tried order, including `whole_match` .
+//! - `errors` has the failures of the other matchers. `explanation` tells why the matcher failed in words.
+//! - `fragments` has the ranges of the `whole_match` , filtered by `--include-frags` / `--exclude-frags` .
+//! - `tree` has the same fragments as `fragments` , nested as in the syntax tree.
+//!   `kind` is the name of the syn type of the node.
+//! - `byte` is a byte offset. `line` is 1-based and `column` is a 0-based count of chars.
+//!   `utf16` is an offset in UTF-16 code units. `end` is exclusive.
+//!
+//! Fields are only added while `version` stays the same.
+
+use crate::Mode;
+use coloring_common::{
+    diagnostics, why_not, Classification, Diagnostic, Edition, FragNode, FragRange, FragSpecs,
+    TopMatch, WholeMatcher,
+};
+use proc_macro2::LineColumn;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+pub struct JsonOutput {
+    pub version: u32,
+    pub edition: Edition,
+    pub whole_match: Option<WholeMatcher>,
+    pub hit_matchers: Vec<WholeMatcher>,
+    pub errors: Vec<JsonError>,
+    pub fragments: BTreeMap<FragSpecs, Vec<JsonRange>>,
+    pub tree: Vec<JsonNode>,
+}
+
+#[derive(Serialize)]
+pub struct JsonNode {
+    pub frags: Vec<FragSpecs>,
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub range: JsonRange,
+    pub children: Vec<JsonNode>,
+}
+
+impl From<FragNode> for JsonNode {
+    fn from(
+        FragNode {
+            frags,
+            kind,
+            range,
+            children,
+        }: FragNode,
+    ) -> Self {
+        Self {
+            frags,
+            kind,
+            range: range.into(),
+            children: children.into_iter().map(JsonNode::from).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct JsonError {
+    pub matcher: WholeMatcher,
+    pub message: String,
+    pub start: Position,
+    pub end: Position,
+    /// Why the matcher failed, in words.
+    pub explanation: String,
+}
+
+#[derive(Serialize)]
+pub struct JsonRange {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Serialize, Clone, Copy)]
+pub struct Position {
+    pub byte: usize,
+    pub line: usize,
+    pub column: usize,
+    pub utf16: usize,
+}
+
+impl Position {
+    fn new(byte: usize, LineColumn { line, column }: LineColumn, utf16: usize) -> Self {
+        Self {
+            byte,
+            line,
+            column,
+            utf16,
+        }
+    }
+}
+
+impl From<FragRange> for JsonRange {
+    fn from(
+        FragRange {
+            byte,
+            start,
+            end,
+            utf16,
+        }: FragRange,
+    ) -> Self {
+        Self {
+            start: Position::new(byte.start, start, utf16.start),
+            end: Position::new(byte.end, end, utf16.end),
+        }
+    }
+}
+
+impl JsonOutput {
+    pub fn new(
+        content: &str,
+        classification: Classification,
+        edition: Edition,
+        mode: &Mode,
+    ) -> Self {
+        let Classification {
+            top_match,
+            hit_matchers,
+            errors,
+        } = classification;
+
+        let (whole_match, fragments, tree) = match top_match {
+            Some(TopMatch {
+                matcher,
+                ranges,
+                tree,
+            }) => {
+                let fragments = ranges
+                    .into_iter()
+                    .filter(|(frag, _)| mode.is_target(*frag))
+                    .map(|(frag, mut ranges)| {
+                        ranges.sort_by_key(|r| (r.byte.start, r.byte.end));
+                        let ranges = ranges.into_iter().map(JsonRange::from).collect();
+
+                        (frag, ranges)
+                    })
+                    .collect();
+
+                let tree = tree
+                    .into_iter()
+                    .flat_map(|node| node.filter(&|frag| mode.is_target(frag)))
+                    .map(JsonNode::from)
+                    .collect();
+
+                (Some(matcher), fragments, tree)
+            }
+            None => (None, BTreeMap::new(), Vec::new()),
+        };
+
+        let why_not = why_not(content, &errors, edition);
+
+        let errors = diagnostics(content, &errors)
+            .into_iter()
+            .map(
+                |Diagnostic {
+                     matcher,
+                     message,
+                     range,
+                 }| {
+                    let JsonRange { start, end } = range.into();
+                    let explanation = why_not
+                        .iter()
+                        .find(|w| w.matcher == matcher)
+                        .map(|w| w.explanation.clone())
+                        .unwrap_or_default();
+
+                    JsonError {
+                        matcher,
+                        message,
+                        start,
+                        end,
+                        explanation,
+                    }
+                },
+            )
+            .collect();
+
+        Self {
+            version: SCHEMA_VERSION,
+            edition,
+            whole_match,
+            hit_matchers,
+            errors,
+            fragments,
+            tree,
+        }
+    }
+}
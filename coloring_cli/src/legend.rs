@@ -0,0 +1,54 @@
+use crate::theme::theme;
+use crate::Mode;
+use coloring_common::{matcher_name, RangeMap, WholeMatcher};
+
+/// `Whole Match: expr (also: stmt, file)`
+pub fn header(top: WholeMatcher, hit_matchers: &[WholeMatcher]) -> String {
+    let others = hit_matchers
+        .iter()
+        .filter(|m| **m != top)
+        .map(|m| matcher_name(*m))
+        .collect::<Vec<_>>();
+
+    if others.is_empty() {
+        return format!("Whole Match: {}", matcher_name(top));
+    }
+
+    format!(
+        "Whole Match: {} (also: {})",
+        matcher_name(top),
+        others.join(", ")
+    )
+}
+
+/// The targets of `mode` , from the highest priority, each in its own style with the number
+/// of ranges found.
+///
+/// ```text
+/// Legend:
+///    ident       2
+///    path        1
+/// ```
+pub fn legend(range_info: &RangeMap, mode: &Mode) -> String {
+    let mut lines = vec!["Legend:".to_string()];
+
+    for frag in theme().priority().iter().rev() {
+        if !mode.is_target(*frag) {
+            continue;
+        }
+
+        let name = frag.to_string().to_ascii_lowercase();
+        let count = range_info.get(frag).map_or(0, |ranges| ranges.len());
+
+        lines.push(format!(
+            "  {} {:>3}",
+            theme()
+                .style(*frag)
+                .to_style()
+                .apply_to(format!(" {:<9} ", name)),
+            count
+        ));
+    }
+
+    lines.join("\n")
+}
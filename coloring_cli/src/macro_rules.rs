@@ -0,0 +1,101 @@
+use crate::{colored_content, print_content, Mode};
+use anyhow::Result;
+use coloring_common::mbe::{
+    ArmError, Binding, BoundFragment, ExpandedToken, Expansion, MacroMatch, MacroRules,
+    NamedBinding, TokenOrigin,
+};
+use coloring_common::Edition;
+
+pub fn macro_rules_mode(
+    invocation: &str,
+    definition: &str,
+    edition: Edition,
+    mode: Mode,
+) -> Result<()> {
+    let rules = MacroRules::parse(definition, edition)?;
+
+    let MacroMatch { matched, errors } = rules.match_invocation(invocation)?;
+
+    for ArmError { arm, error } in errors {
+        println!("Arm #{} failed at {}", arm, located(&error));
+    }
+
+    let Some(arm_match) = matched else {
+        return Err(anyhow::anyhow!("No arms matched!"));
+    };
+
+    let matcher = &rules.arms[arm_match.arm].matcher;
+    println!(
+        "Arm #{} matched: {}",
+        arm_match.arm,
+        &definition[matcher.span().byte_range()]
+    );
+
+    for NamedBinding {
+        name,
+        kind,
+        binding,
+    } in arm_match.bindings.iter()
+    {
+        print_binding(&format!("${}:{}", name, kind), binding, invocation, 0);
+    }
+
+    let res = colored_content(invocation, arm_match.ranges(), &mode).concat();
+
+    print_content(&res);
+
+    let Expansion { tokens, annotated } = rules
+        .transcribe(&arm_match)
+        .map_err(|e| anyhow::anyhow!("Transcription failed at {}", located(&e)))?;
+
+    println!("Expansion: {}", tokens);
+
+    for ExpandedToken { text, origin } in annotated {
+        match origin {
+            TokenOrigin::Transcriber(byte_range) => {
+                println!(
+                    "{:<16} <- transcriber ({}..{})",
+                    text, byte_range.start, byte_range.end
+                )
+            }
+            TokenOrigin::MetaVar { name, byte_range } => {
+                println!(
+                    "{:<16} <- ${} ({}..{})",
+                    text, name, byte_range.start, byte_range.end
+                )
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_binding(label: &str, binding: &Binding, invocation: &str, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    match binding {
+        Binding::Single(BoundFragment { byte_range, .. }) => {
+            println!(
+                "{}{} = `{}` ({}..{})",
+                indent,
+                label,
+                &invocation[byte_range.clone()],
+                byte_range.start,
+                byte_range.end
+            );
+        }
+        Binding::Seq(seq) => {
+            println!("{}{} ({} times)", indent, label, seq.len());
+
+            for (i, binding) in seq.iter().enumerate() {
+                print_binding(&format!("[{}]", i), binding, invocation, depth + 1);
+            }
+        }
+    }
+}
+
+fn located(error: &syn::Error) -> String {
+    let start = error.span().start();
+
+    format!("{}:{}: {}", start.line, start.column + 1, error)
+}
@@ -1,27 +1,150 @@
+mod all_matches;
+mod batch;
+mod diagnostic;
+mod html;
+mod json;
+mod legend;
+mod macro_rules;
+mod nesting;
+mod outline;
+mod recovery;
+mod selection;
+mod stats;
+mod terminal;
+mod theme;
+
 use std::collections::HashSet;
-use std::ops::Range;
-use std::path::PathBuf;
-use std::{collections::HashMap, sync::LazyLock};
-
-use anyhow::Result;
-use clap::{Args, Parser};
-use coloring_common::{Colored, FragSpecs};
-use coloring_macro::repeat_for_types;
-use console::{Color, Style, StyledObject};
-use dialoguer::Input;
-use quote::ToTokens;
-use syn::parse::Parse;
+use std::io::{BufRead, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, ValueEnum};
+use coloring_common::{
+    classify, diagnostics, matcher_name, paint, Classification, Edition, FragSpecs, RangeMap,
+    WholeMatcher, WhyNot,
+};
+use nesting::Nesting;
+use selection::Selection;
+use terminal::ColorDepth;
+use theme::{theme, Preset};
 
 #[derive(Parser)]
 #[command(version, about)]
 struct Cli {
+    /// Rust code. `-` reads it from stdin, which is also read when it is piped
     content: Option<String>,
 
+    /// File of Rust code. `-` reads it from stdin
     #[arg(short, long)]
     file_path: Option<PathBuf>,
 
+    /// Fail instead of asking for the content when none is given
+    #[arg(long)]
+    no_interactive: bool,
+
+    #[arg(long, value_enum, default_value_t)]
+    edition: Edition,
+
+    /// File of a `macro_rules!` definition to match the content against as its invocation
+    #[arg(short, long)]
+    macro_rules: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t, conflicts_with = "macro_rules")]
+    format: Format,
+
+    /// Emit a whole HTML document with a stylesheet ( `--format html` only )
+    #[arg(long)]
+    standalone: bool,
+
+    /// How fragments nested in one another are shown ( `--format ansi` only )
+    #[arg(long, value_enum, default_value_t)]
+    nesting: Nesting,
+
+    /// Classify only this byte range of the content in the context of the whole file, e.g. `120..184`
+    #[arg(long, value_parser = selection::parse_range, conflicts_with_all = ["lines", "macro_rules", "format"])]
+    range: Option<Selection>,
+
+    /// Same as `--range` but by `line:column` ( 1-based line, 0-based column in chars ), e.g. `10:4-12:9`
+    #[arg(long, value_parser = selection::parse_lines, conflicts_with_all = ["macro_rules", "format"])]
+    lines: Option<Selection>,
+
+    /// Matchers to try in order, e.g. `ty,expr` . The first one accepting the whole content is used.
+    /// All of them by default
+    #[arg(long, value_delimiter = ',')]
+    matchers: Option<Vec<WholeMatcher>>,
+
+    /// Show the result of every matcher accepting the whole content, not only the first one
+    /// ( `--format ansi` and `--format tree` only )
+    #[arg(long, conflicts_with_all = ["macro_rules", "range", "lines"])]
+    all_matches: bool,
+
+    /// Print the matcher used above the content, and the colors and the numbers of the fragments
+    /// below it ( `--format ansi` only ). Printed without this when the colors are detected to be
+    /// unavailable
+    #[arg(long, conflicts_with_all = ["macro_rules", "range", "lines", "all_matches"])]
+    legend: bool,
+
+    /// Print the metrics of the fragments instead of the content ( `--format ansi` and `--format json` only )
+    #[arg(long, conflicts_with_all = ["macro_rules", "range", "lines", "all_matches", "legend"])]
+    stats: bool,
+
+    /// Theme file. `~/.config/coloring_rust/theme.toml` is used if it exists
+    ///
+    /// A TOML file like the following. Tables are named by fragment specifiers,
+    /// and omitted fields are taken from the preset.
+    ///
+    ///     preset = "dark"               # "classic", "dark" or "light"
+    ///     priority = ["item", "block"]  # same as `--priority`
+    ///
+    ///     [expr]
+    ///     color = "#00ced1"             # "#rrggbb", a 256-color index like 44, or a name like "cyan"
+    ///     target = "bg"                 # "fg" (default) or "bg"
+    ///     bold = true
+    ///     underline = false
+    ///     italic = false
+    #[arg(long, verbatim_doc_comment)]
+    theme: Option<PathBuf>,
+
+    /// Colors of the terminal. Detected from `NO_COLOR` , `COLORTERM` , `TERM` etc. by default
+    #[arg(long, value_enum)]
+    color_depth: Option<ColorDepth>,
+
+    /// Built-in theme, which a theme file is based on
+    #[arg(long, value_enum)]
+    preset: Option<Preset>,
+
+    /// Drawing order of highlights from the lowest to the highest, e.g. `item,block,expr` .
+    /// Unlisted ones are drawn below them in the default order
+    #[arg(long, value_delimiter = ',')]
+    priority: Option<Vec<FragSpecs>>,
+
+    /// Explain to stderr why the other matchers do not accept the content
+    #[arg(long)]
+    why_not: bool,
+
+    /// When every matcher fails, highlight the parseable top-level pieces and show the errors inline
+    #[arg(long)]
+    recover: bool,
+
     #[command(flatten)]
     include_exclude: IncludeExclude,
+
+    #[command(flatten)]
+    batch: batch::Batch,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+enum Format {
+    /// Colored content for terminals
+    #[default]
+    Ansi,
+    /// Classification result as JSON. The schema is versioned by its `version` field and
+    /// described in the README
+    Json,
+    /// `<span>` tagged HTML
+    Html,
+    /// Outline of the nested fragments
+    Tree,
 }
 
 #[derive(Args)]
@@ -39,6 +162,15 @@ enum Mode {
     Exclude(HashSet<FragSpecs>),
 }
 
+impl Mode {
+    fn is_target(&self, frag: FragSpecs) -> bool {
+        match self {
+            Mode::Include(inc) => inc.contains(&frag),
+            Mode::Exclude(exc) => !exc.contains(&frag),
+        }
+    }
+}
+
 impl IncludeExclude {
     fn into_mode(self) -> Mode {
         match (self.exclude_frags, self.include_frags) {
@@ -53,221 +185,276 @@ fn main() -> Result<()> {
     let Cli {
         content,
         file_path,
+        no_interactive,
+        edition,
+        macro_rules,
+        format,
+        standalone,
+        nesting,
+        range,
+        lines,
+        matchers,
+        all_matches,
+        legend,
+        stats,
+        recover,
+        color_depth,
+        theme,
+        preset,
+        priority,
+        why_not,
         include_exclude,
+        batch,
     } = Cli::parse();
 
+    // written to files, which are not read by this terminal
+    let color_depth = match (&batch.paths, format) {
+        (Some(_), Format::Ansi | Format::Tree) => color_depth.or(Some(ColorDepth::Ansi256)),
+        _ => color_depth,
+    };
+
+    terminal::init(color_depth)?;
+    theme::init(theme, preset, priority)?;
+
     let mode = include_exclude.into_mode();
 
-    let content = get_content(content, file_path)?;
+    if batch.paths.is_some() {
+        return batch::batch_mode(batch, matchers, edition, format, standalone, nesting, mode);
+    }
 
-    let range_info = parser(&content)?;
+    let matchers = matchers.unwrap_or_else(|| WholeMatcher::DEFAULT_ORDER.to_vec());
 
-    let colored = colored_content(&content, range_info, mode);
+    let origin = match &file_path {
+        Some(path) if is_stdin(path) => "<stdin>".to_string(),
+        Some(path) => path.display().to_string(),
+        None => "<input>".to_string(),
+    };
+    let content = get_content(content, file_path, !no_interactive)?;
 
-    let res = colored
-        .into_iter()
-        .map(|c| c.to_string())
-        .collect::<String>();
+    if all_matches && !matches!(format, Format::Ansi | Format::Tree) {
+        return Err(anyhow!(
+            "`--all-matches` is only for `--format ansi` and `--format tree`"
+        ));
+    }
 
-    println!("{}", res);
+    if legend && !matches!(format, Format::Ansi) {
+        return Err(anyhow!("`--legend` is only for `--format ansi`"));
+    }
 
-    Ok(())
-}
+    // plain text tells the fragments only with the legend
+    let legend = legend || (color_depth.is_none() && terminal::color_depth() == ColorDepth::None);
 
-fn get_content(content: Option<String>, file_path: Option<PathBuf>) -> Result<String> {
-    let res = match (file_path, content) {
-        (Some(path), _) => std::fs::read_to_string(path)?,
-        (_, Some(content)) => content,
-        _ => Input::new().with_prompt("Rust?").interact_text()?,
-    };
+    if stats && !matches!(format, Format::Ansi | Format::Json) {
+        return Err(anyhow!(
+            "`--stats` is only for `--format ansi` and `--format json`"
+        ));
+    }
 
-    Ok(res)
-}
+    if let Some(path) = macro_rules {
+        let definition = std::fs::read_to_string(path)?;
+
+        return macro_rules::macro_rules_mode(&content, &definition, edition, mode);
+    }
+
+    if let Some(selection) = range.or(lines) {
+        return selection::selection_mode(&content, selection, &matchers, edition, mode);
+    }
 
-#[derive(Debug)]
-struct CustomPat(syn::Pat);
+    if let (Format::Json, false) = (format, stats) {
+        let classification = classify(&content, &matchers, edition);
+        let output = json::JsonOutput::new(&content, classification, edition, &mode);
 
-impl Parse for CustomPat {
-    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        if let Ok(res) = syn::Pat::parse_multi_with_leading_vert(input) {
-            return Ok(CustomPat(res));
+        println!("{}", serde_json::to_string_pretty(&output)?);
+
+        return Ok(());
+    }
+
+    let Classification {
+        top_match,
+        hit_matchers,
+        errors,
+    } = classify(&content, &matchers, edition);
+
+    let Some(top_match) = top_match else {
+        if recover && matches!(format, Format::Ansi | Format::Html) {
+            return recovery::recovery_mode(&content, &matchers, edition, format, standalone, mode);
         }
 
-        if let Ok(res) = syn::Pat::parse_multi(input) {
-            return Ok(CustomPat(res));
+        eprint!(
+            "{}",
+            diagnostic::render(
+                &content,
+                &origin,
+                &diagnostics(&content, &errors),
+                &coloring_common::why_not(&content, &errors, edition),
+            )
+        );
+
+        return Err(anyhow!("All Match Failed!"));
+    };
+
+    if why_not {
+        eprintln!("Whole Match: {}", matcher_name(top_match.matcher));
+        eprintln!("Why not:");
+        for WhyNot { explanation, .. } in coloring_common::why_not(&content, &errors, edition) {
+            eprintln!("  - {}", explanation);
         }
+    }
 
-        let res = syn::Pat::parse_single(input)?;
+    if stats {
+        return stats::stats_mode(&content, top_match, format, &mode);
+    }
 
-        Ok(CustomPat(res))
+    if all_matches {
+        return all_matches::all_matches_mode(
+            &content,
+            &hit_matchers,
+            edition,
+            format,
+            nesting,
+            mode,
+        );
     }
-}
 
-impl ToTokens for CustomPat {
-    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-        self.0.to_tokens(tokens);
+    if let Format::Tree = format {
+        let tree = top_match
+            .tree
+            .into_iter()
+            .flat_map(|node| node.filter(&|frag| mode.is_target(frag)))
+            .collect::<Vec<_>>();
+
+        println!("{}", outline::outline(&content, &tree));
+
+        return Ok(());
     }
+
+    let mut range_info = top_match.byte_ranges();
+
+    if let Format::Html = format {
+        range_info.retain(|frag, _| mode.is_target(*frag));
+
+        println!("{}", html::html_content(&content, range_info, standalone));
+
+        return Ok(());
+    }
+
+    if legend {
+        println!("{}", legend::header(top_match.matcher, &hit_matchers));
+    }
+
+    let res = nesting::nested_content(&content, range_info.clone(), top_match.tree, nesting, &mode);
+
+    print_content(&res);
+
+    if legend {
+        println!("{}", legend::legend(&range_info, &mode));
+    }
+
+    Ok(())
 }
 
-fn parser(content: &str) -> Result<HashMap<FragSpecs, Vec<Range<usize>>>> {
-    let mut errors = Vec::new();
-
-    repeat_for_types!(for T in [
-        syn::File,
-        syn::Item,
-        syn::Block,
-        syn::Stmt,
-        syn::Expr,
-        syn::Type,
-        syn::Path,
-        syn::Visibility,
-        syn::Ident,
-        syn::Lifetime,
-        syn::Lit,
-        syn::Meta,
-        // syn::Pat,
-        // CustomPat,
-    ] {
-        let e = match syn::parse_str::<T>(content) {
-            Ok(p) => {
-                let mut colored = Colored::<T>::new(p);
-                colored.visit();
-
-                return Ok(colored.ranges);
+fn get_content(
+    content: Option<String>,
+    file_path: Option<PathBuf>,
+    interactive: bool,
+) -> Result<String> {
+    let stdin = std::io::stdin();
+    let piped = !stdin.is_terminal();
+
+    read_content(content, file_path, interactive, piped, stdin.lock())
+}
+
+/// [`get_content`] reading `stdin` , which is `piped` unless it is a terminal.
+fn read_content(
+    content: Option<String>,
+    file_path: Option<PathBuf>,
+    interactive: bool,
+    piped: bool,
+    mut stdin: impl BufRead,
+) -> Result<String> {
+    let no_content = || {
+        anyhow!("No content is given. Pass it as an argument, by `--file-path` or through stdin")
+    };
+
+    let res = match (file_path, content) {
+        (Some(path), _) if is_stdin(&path) => read_stdin(&mut stdin)?,
+        (Some(path), _) => std::fs::read_to_string(path)?,
+        (_, Some(content)) if content == "-" => read_stdin(&mut stdin)?,
+        (_, Some(content)) => content,
+        _ if piped => {
+            let res = read_stdin(&mut stdin)?;
+
+            // nothing piped, e.g. `< /dev/null` in a script
+            if !interactive && res.trim().is_empty() {
+                return Err(no_content());
             }
-            Err(e) => e,
-        };
-        errors.push(e);
-    });
 
-    Err(anyhow::anyhow!("All Match Failed!: {:?}", errors))
+            res
+        }
+        _ if !interactive => return Err(no_content()),
+        _ => read_lines(stdin)?,
+    };
+
+    Ok(res)
 }
 
-#[derive(Debug, Clone, Copy)]
-enum BgFg {
-    Bg,
-    Fg,
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
 }
 
-#[derive(Debug, Clone, Copy)]
-struct CustomStyle {
-    color: Color,
-    bgfg: BgFg,
+fn read_stdin(stdin: &mut impl Read) -> Result<String> {
+    let mut res = String::new();
+    stdin.read_to_string(&mut res)?;
+
+    Ok(res)
 }
 
-impl CustomStyle {
-    fn new(color: Color) -> Self {
-        Self {
-            color,
-            bgfg: BgFg::Fg,
+/// Asks for the content line by line until an empty line or EOF.
+fn read_lines(stdin: impl BufRead) -> Result<String> {
+    eprintln!("Rust? ( an empty line or EOF to finish )");
+
+    let mut lines = Vec::new();
+    for line in stdin.lines() {
+        let line = line?;
+        if line.is_empty() {
+            break;
         }
+        lines.push(line);
     }
 
-    fn set_bgfg(self, bgfg: BgFg) -> Self {
-        Self { bgfg, ..self }
-    }
+    Ok(lines.join("\n"))
+}
 
-    fn to_style(self) -> Style {
-        match self.bgfg {
-            BgFg::Bg => Style::new().bg(self.color),
-            BgFg::Fg => Style::new().fg(self.color),
-        }
+/// Prints colored content without adding a newline to one already ending with it.
+fn print_content(res: &str) {
+    if res.ends_with('\n') {
+        print!("{}", res);
+    } else {
+        println!("{}", res);
     }
 }
 
-static COLOR_MAP: LazyLock<HashMap<FragSpecs, CustomStyle>> = LazyLock::new(|| {
-    HashMap::from_iter([
-        (FragSpecs::Block, CustomStyle::new(Color::Blue)),
-        (
-            FragSpecs::Expr,
-            CustomStyle::new(Color::Blue).set_bgfg(BgFg::Bg),
-        ),
-        (
-            FragSpecs::Ident,
-            CustomStyle::new(Color::Yellow).set_bgfg(BgFg::Bg),
-        ),
-        (FragSpecs::Item, CustomStyle::new(Color::Yellow)),
-        (
-            FragSpecs::Lifetime,
-            CustomStyle::new(Color::Red).set_bgfg(BgFg::Bg),
-        ),
-        (
-            FragSpecs::Literal,
-            CustomStyle::new(Color::Magenta).set_bgfg(BgFg::Bg),
-        ),
-        (FragSpecs::Meta, CustomStyle::new(Color::Magenta)),
-        (
-            FragSpecs::Pat,
-            CustomStyle::new(Color::Green).set_bgfg(BgFg::Bg),
-        ),
-        (FragSpecs::Path, CustomStyle::new(Color::Green)),
-        (
-            FragSpecs::Stmt,
-            CustomStyle::new(Color::Cyan).set_bgfg(BgFg::Bg),
-        ),
-        (
-            FragSpecs::Ty,
-            CustomStyle::new(Color::Yellow).set_bgfg(BgFg::Bg),
-        ),
-        (FragSpecs::Vis, CustomStyle::new(Color::Yellow)),
-    ])
-});
-
-const ORDERED_FRAG_SPECS: [FragSpecs; 12] = [
-    FragSpecs::Item,
-    FragSpecs::Block,
-    FragSpecs::Meta,
-    FragSpecs::Stmt,
-    FragSpecs::Expr,
-    FragSpecs::Vis,
-    FragSpecs::Lifetime,
-    FragSpecs::Ty,
-    FragSpecs::Pat,
-    FragSpecs::Path,
-    FragSpecs::Ident,
-    FragSpecs::Literal,
-];
-
-fn colored_content(
-    content: &str,
-    range_info: HashMap<FragSpecs, Vec<Range<usize>>>,
-    mode: Mode,
-) -> Vec<StyledObject<char>> {
-    let mut styles: Vec<Option<Style>> = vec![None; content.bytes().len()];
-
-    for frag in ORDERED_FRAG_SPECS {
-        match &mode {
-            Mode::Include(inc) => {
-                if !inc.contains(&frag) {
-                    continue;
-                }
-            }
-            Mode::Exclude(exc) => {
-                if exc.contains(&frag) {
-                    continue;
-                }
-            }
-        }
+/// Styled chars of `content` .
+fn colored_content(content: &str, range_info: RangeMap, mode: &Mode) -> Vec<String> {
+    let frags = theme()
+        .priority()
+        .iter()
+        .copied()
+        .filter(|frag| mode.is_target(*frag))
+        .collect::<Vec<_>>();
 
-        let style = COLOR_MAP.get(&frag).unwrap().to_style();
-
-        if let Some(ranges) = range_info.get(&frag) {
-            for range in ranges {
-                for i in range.clone() {
-                    styles[i] = Some(style.clone());
-                }
-            }
-        }
-    }
+    let painted = paint(content.len(), &range_info, &frags);
 
     let res = content
         .char_indices()
         .map(|(i, c)| {
             if c == '\n' {
-                return Style::new().apply_to(c);
+                return c.to_string();
             }
 
-            let style = styles[i].clone().unwrap_or_default();
+            let style = painted[i]
+                .map(|frag| theme().style(frag).to_style())
+                .unwrap_or_default();
             style.apply_to(c)
         })
         .collect();
@@ -280,3 +467,67 @@ type 日本語名の型 = i32;
 
 #[allow(unused)]
 fn 日本語名の関数() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(
+        content: Option<&str>,
+        file_path: Option<&str>,
+        interactive: bool,
+        piped: bool,
+        stdin: &str,
+    ) -> Result<String> {
+        read_content(
+            content.map(str::to_string),
+            file_path.map(PathBuf::from),
+            interactive,
+            piped,
+            stdin.as_bytes(),
+        )
+    }
+
+    #[test]
+    fn argument_wins_over_stdin() {
+        assert_eq!(read(Some("x + 1"), None, true, true, "y").unwrap(), "x + 1");
+        assert_eq!(
+            read(Some("x + 1"), None, false, false, "").unwrap(),
+            "x + 1"
+        );
+    }
+
+    #[test]
+    fn dash_reads_stdin() {
+        let stdin = "fn f() {}\n\nfn g() {}\n";
+
+        assert_eq!(read(Some("-"), None, false, false, stdin).unwrap(), stdin);
+        assert_eq!(read(None, Some("-"), false, false, stdin).unwrap(), stdin);
+    }
+
+    #[test]
+    fn piped_stdin_is_read_whole() {
+        let stdin = "fn f() {}\n\nfn g() {}\n";
+
+        assert_eq!(read(None, None, true, true, stdin).unwrap(), stdin);
+        assert_eq!(read(None, None, false, true, stdin).unwrap(), stdin);
+    }
+
+    #[test]
+    fn no_interactive_fails_without_content() {
+        assert!(read(None, None, false, false, "x").is_err());
+        assert!(read(None, None, false, true, " \n").is_err());
+
+        // empty stdin is the content when asking is allowed
+        assert_eq!(read(None, None, true, true, "").unwrap(), "");
+    }
+
+    #[test]
+    fn interactive_reads_until_an_empty_line() {
+        assert_eq!(
+            read(None, None, true, false, "fn f() {\n}\n\nignored\n").unwrap(),
+            "fn f() {\n}"
+        );
+        assert_eq!(read(None, None, true, false, "x\r\ny").unwrap(), "x\ny");
+    }
+}
@@ -0,0 +1,134 @@
+use crate::terminal::TermStyle;
+use crate::theme::theme;
+use crate::{colored_content, Mode};
+use clap::ValueEnum;
+use coloring_common::{FragNode, FragSpecs, RangeMap};
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Nesting {
+    /// Only the fragment with the highest priority at each char
+    #[default]
+    Flat,
+    /// The innermost node as the foreground, the next one as the background
+    /// and the third one as an underline
+    Layered,
+    /// Every fragment wrapped in brackets with its names, e.g. `⟦expr ⟦path ⟦ident x⟧⟧⟧`
+    Brackets,
+}
+
+/// Colored content for terminals, showing nested fragments as `nesting` says.
+pub fn nested_content(
+    content: &str,
+    range_info: RangeMap,
+    tree: Vec<FragNode>,
+    nesting: Nesting,
+    mode: &Mode,
+) -> String {
+    if let Nesting::Flat = nesting {
+        return colored_content(content, range_info, mode).concat();
+    }
+
+    let tree = tree
+        .into_iter()
+        .flat_map(|node| node.filter(&|frag| mode.is_target(frag)))
+        .collect::<Vec<_>>();
+
+    match nesting {
+        Nesting::Layered => layered_content(content, &tree),
+        _ => bracketed_content(content, &tree),
+    }
+}
+
+/// Each node is a layer drawn as its fragment with the highest priority.
+/// Layers deeper than the third one from the innermost are not shown.
+fn layered_content(content: &str, tree: &[FragNode]) -> String {
+    // outermost first
+    let mut layers = vec![Vec::new(); content.len()];
+
+    for root in tree {
+        root.walk(&mut |node, _| {
+            let Some(frag) = top_frag(&node.frags) else {
+                return;
+            };
+            for layer in &mut layers[node.range.byte.clone()] {
+                layer.push(frag);
+            }
+        });
+    }
+
+    content
+        .char_indices()
+        .map(|(i, c)| {
+            if c == '\n' {
+                return c.to_string();
+            }
+
+            let color = |frag: FragSpecs| theme().style(frag).color;
+            let mut inner = layers[i].iter().rev();
+
+            let mut style = TermStyle::new();
+            if let Some(frag) = inner.next() {
+                style = style.fg(color(*frag));
+            }
+            if let Some(frag) = inner.next() {
+                style = style.bg(color(*frag));
+            }
+            if inner.next().is_some() {
+                style = style.underlined();
+            }
+
+            style.apply_to(c)
+        })
+        .collect()
+}
+
+fn bracketed_content(content: &str, tree: &[FragNode]) -> String {
+    let mut res = String::new();
+    let end = bracket_nodes(content, tree, 0, &mut res);
+    res.push_str(&content[end..]);
+
+    res
+}
+
+/// Pushes `content` from `from` to the end of the last of `nodes` , and returns that end.
+fn bracket_nodes(content: &str, nodes: &[FragNode], mut from: usize, res: &mut String) -> usize {
+    let mut nodes = nodes.iter().collect::<Vec<_>>();
+    nodes.sort_by_key(|node| node.range.byte.start);
+
+    for node in nodes {
+        let byte = &node.range.byte;
+        if byte.start < from {
+            continue;
+        }
+
+        res.push_str(&content[from..byte.start]);
+
+        let style = top_frag(&node.frags)
+            .map(|f| theme().style(f).to_style())
+            .unwrap_or_default();
+
+        let names = node
+            .frags
+            .iter()
+            .map(|f| f.to_string().to_ascii_lowercase())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        res.push_str(&style.apply_to(format!("⟦{} ", names)));
+        let inner_end = bracket_nodes(content, &node.children, byte.start, res);
+        res.push_str(&content[inner_end..byte.end.max(inner_end)]);
+        res.push_str(&style.apply_to("⟧"));
+
+        from = byte.end.max(inner_end);
+    }
+
+    from
+}
+
+/// The fragment with the highest priority.
+fn top_frag(frags: &[FragSpecs]) -> Option<FragSpecs> {
+    frags
+        .iter()
+        .copied()
+        .max_by_key(|f| theme().priority().iter().position(|o| o == f))
+}
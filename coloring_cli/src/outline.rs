@@ -0,0 +1,15 @@
+use crate::theme::theme;
+use coloring_common::FragNode;
+
+/// [`coloring_common::outline`] with each snippet styled as the fragment with the highest priority.
+pub fn outline(content: &str, tree: &[FragNode]) -> String {
+    coloring_common::outline(content, tree, |frags, snippet| {
+        let style = frags
+            .iter()
+            .max_by_key(|f| theme().priority().iter().position(|o| o == *f))
+            .map(|f| theme().style(*f).to_style())
+            .unwrap_or_default();
+
+        style.apply_to(snippet)
+    })
+}
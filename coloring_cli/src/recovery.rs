@@ -0,0 +1,69 @@
+use crate::{colored_content, html, Format, Mode};
+use anyhow::Result;
+use coloring_common::{recover, Edition, Piece, WholeMatcher};
+use console::Style;
+
+/// Highlights the parseable pieces of broken code and shows the errors of the others inline.
+pub fn recovery_mode(
+    content: &str,
+    matchers: &[WholeMatcher],
+    edition: Edition,
+    format: Format,
+    standalone: bool,
+    mode: Mode,
+) -> Result<()> {
+    let mut recovered = recover(content, matchers, edition);
+
+    if let Format::Html = format {
+        recovered.retain_frags(|frag| mode.is_target(frag));
+
+        println!(
+            "{}",
+            html::recovered_html_content(content, &recovered, standalone)
+        );
+
+        return Ok(());
+    }
+
+    let colored = colored_content(content, recovered.byte_ranges(), &mode);
+
+    let error_style = Style::new().red().underlined();
+    let message_style = Style::new().red().italic();
+
+    let mut pieces = recovered.pieces.iter().filter_map(|piece| match piece {
+        Piece::Failed { range, message, .. } => Some((range, message)),
+        Piece::Parsed { .. } => None,
+    });
+    let mut failed = pieces.next();
+
+    let mut res = String::new();
+    for ((i, c), styled) in content.char_indices().zip(colored) {
+        while let Some((_, message)) = failed.filter(|(range, _)| range.end <= i) {
+            res.push_str(
+                &message_style
+                    .apply_to(format!(" ⟨{}⟩", message))
+                    .to_string(),
+            );
+            failed = pieces.next();
+        }
+
+        match failed {
+            Some((range, _)) if range.contains(&i) && c != '\n' => {
+                res.push_str(&error_style.apply_to(c).to_string());
+            }
+            _ => res.push_str(&styled),
+        }
+    }
+
+    for (_, message) in failed.into_iter().chain(pieces) {
+        res.push_str(
+            &message_style
+                .apply_to(format!(" ⟨{}⟩", message))
+                .to_string(),
+        );
+    }
+
+    println!("{}", res);
+
+    Ok(())
+}
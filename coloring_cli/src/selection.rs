@@ -0,0 +1,218 @@
+use crate::{colored_content, print_content, Mode};
+use anyhow::{anyhow, Result};
+use coloring_common::{
+    classify_selection, matcher_name, Classification, Edition, FragmentAt, Offset,
+    SelectionClassification, WholeMatcher,
+};
+use std::ops::Range;
+
+/// Selected part of the content given by `--range` or `--lines` .
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Bytes(usize, usize),
+    Lines(Offset, Offset),
+}
+
+/// `120..184`
+pub fn parse_range(s: &str) -> Result<Selection> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| anyhow!("expected `start..end`"))?;
+
+    Ok(Selection::Bytes(start.trim().parse()?, end.trim().parse()?))
+}
+
+/// `10:4-12:9` , where lines are 1-based and columns are 0-based counts of chars.
+pub fn parse_lines(s: &str) -> Result<Selection> {
+    let parse_position = |s: &str| -> Result<Offset> {
+        let (line, column) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected `line:column`"))?;
+
+        Ok(Offset::LineColumn {
+            line: line.trim().parse()?,
+            column: column.trim().parse()?,
+        })
+    };
+
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| anyhow!("expected `line:column-line:column`"))?;
+
+    Ok(Selection::Lines(
+        parse_position(start)?,
+        parse_position(end)?,
+    ))
+}
+
+impl Selection {
+    fn to_byte_range(self, content: &str) -> Result<Range<usize>> {
+        let (start, end) = match self {
+            Selection::Bytes(start, end) => {
+                (Offset::Byte { byte: start }, Offset::Byte { byte: end })
+            }
+            Selection::Lines(start, end) => (start, end),
+        };
+
+        let to_byte = |offset: Offset| {
+            offset.to_byte(content).ok_or_else(|| {
+                anyhow!(
+                    "{:?} is out of the content or not on a char boundary",
+                    offset
+                )
+            })
+        };
+
+        let range = to_byte(start)?..to_byte(end)?;
+
+        if range.start > range.end {
+            return Err(anyhow!("the selection {:?} is reversed", range));
+        }
+
+        Ok(range)
+    }
+}
+
+pub fn selection_mode(
+    content: &str,
+    selection: Selection,
+    matchers: &[WholeMatcher],
+    edition: Edition,
+    mode: Mode,
+) -> Result<()> {
+    let range = selection.to_byte_range(content)?;
+
+    let classified = classify_selection(content, range, matchers, edition);
+    let explanation = classified.explanation();
+
+    let SelectionClassification {
+        selection,
+        exact,
+        enclosing,
+        standalone:
+            Classification {
+                top_match,
+                hit_matchers,
+                ..
+            },
+        ..
+    } = classified;
+
+    if selection.is_empty() {
+        return Err(anyhow!("the selection is empty"));
+    }
+
+    println!("Selection: {}..{}", selection.start, selection.end);
+
+    if !exact.is_empty() {
+        println!("In the file: {}", frag_list(&exact));
+    }
+    if let Some(explanation) = explanation {
+        println!("In the file: {}", explanation);
+    }
+    if !enclosing.is_empty() {
+        println!("Enclosed by: {}", frag_list(&enclosing));
+    }
+
+    let Some(top_match) = top_match else {
+        return Err(anyhow!("All Match Failed for the selection alone!"));
+    };
+
+    let hit_matchers = hit_matchers
+        .iter()
+        .map(|m| matcher_name(*m))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "Whole Match: {} ( hit: {} )",
+        matcher_name(top_match.matcher),
+        hit_matchers
+    );
+
+    let res = colored_content(&content[selection], top_match.byte_ranges(), &mode).concat();
+
+    print_content(&res);
+
+    Ok(())
+}
+
+/// `expr (ExprCall 10..20), stmt (Local 8..21)`
+fn frag_list(frags: &[FragmentAt]) -> String {
+    frags
+        .iter()
+        .map(|FragmentAt { frag, kind, range }| {
+            format!(
+                "{} ({} {}..{})",
+                frag.to_string().to_ascii_lowercase(),
+                kind,
+                range.byte.start,
+                range.byte.end
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("120..184").unwrap(), Selection::Bytes(120, 184));
+        assert_eq!(parse_range(" 0 .. 3 ").unwrap(), Selection::Bytes(0, 3));
+
+        for s in ["120", "120..", "..184", "a..b", "1..=2", "-1..2"] {
+            assert!(parse_range(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            parse_lines("10:4-12:9").unwrap(),
+            Selection::Lines(
+                Offset::LineColumn {
+                    line: 10,
+                    column: 4
+                },
+                Offset::LineColumn {
+                    line: 12,
+                    column: 9
+                },
+            )
+        );
+
+        for s in ["10:4", "10-12", "10:4-12", "10:4-12:x", "10:-4-12:9"] {
+            assert!(parse_lines(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn byte_ranges() {
+        let content = "fn f() {\r\n    é + 1\r\n}\r\n";
+
+        assert_eq!(
+            parse_lines("2:4-2:9")
+                .unwrap()
+                .to_byte_range(content)
+                .unwrap(),
+            14..20
+        );
+        assert_eq!(
+            parse_range("14..20")
+                .unwrap()
+                .to_byte_range(content)
+                .unwrap(),
+            14..20
+        );
+
+        // the middle of `é` , past the end, reversed and past the end of a line
+        for s in ["15..20", "14..100"] {
+            assert!(parse_range(s).unwrap().to_byte_range(content).is_err());
+        }
+        for s in ["2:9-2:4", "2:4-2:10", "5:0-5:1"] {
+            assert!(parse_lines(s).unwrap().to_byte_range(content).is_err());
+        }
+    }
+}
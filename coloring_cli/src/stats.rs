@@ -0,0 +1,92 @@
+use crate::theme::theme;
+use crate::{Format, Mode};
+use anyhow::Result;
+use coloring_common::{matcher_name, FragStats, Stats, TopMatch, WholeMatcher};
+use serde::Serialize;
+
+/// Kinds shown in a row of the table. JSON has all of them.
+const TOP_KINDS: usize = 3;
+
+#[derive(Serialize)]
+struct StatsOutput {
+    whole_match: WholeMatcher,
+    #[serde(flatten)]
+    stats: Stats,
+}
+
+/// Prints the metrics of the fragments of `top_match` as a table, or as JSON for `--format json` .
+pub fn stats_mode(content: &str, top_match: TopMatch, format: Format, mode: &Mode) -> Result<()> {
+    let TopMatch { matcher, tree, .. } = top_match;
+
+    let tree = tree
+        .into_iter()
+        .flat_map(|node| node.filter(&|frag| mode.is_target(frag)))
+        .collect::<Vec<_>>();
+    let stats = coloring_common::stats(content.len(), &tree);
+
+    if let Format::Json = format {
+        let output = StatsOutput {
+            whole_match: matcher,
+            stats,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+
+        return Ok(());
+    }
+
+    println!("Whole Match: {}", matcher_name(matcher));
+    println!();
+    print!("{}", table(&stats));
+
+    Ok(())
+}
+
+/// One row per fragment specifier found, from the highest priority.
+///
+/// ```text
+/// fragment     count    bytes  coverage  depth  kinds
+/// expr            12      140     58.3%      3  ExprPath 5, ExprLit 4, ExprCall 2, ...
+/// ```
+pub fn table(stats: &Stats) -> String {
+    let mut res = format!(
+        "{:<10} {:>7} {:>8} {:>9} {:>6}  kinds\n",
+        "fragment", "count", "bytes", "coverage", "depth"
+    );
+
+    for frag in theme().priority().iter().rev() {
+        let Some(FragStats {
+            count,
+            coverage,
+            max_depth,
+            kinds,
+        }) = stats.frags.get(frag)
+        else {
+            continue;
+        };
+
+        let mut kinds = kinds.iter().collect::<Vec<_>>();
+        kinds.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+
+        let mut kinds_str = kinds
+            .iter()
+            .take(TOP_KINDS)
+            .map(|(kind, count)| format!("{} {}", kind, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if kinds.len() > TOP_KINDS {
+            kinds_str.push_str(", ...");
+        }
+
+        res.push_str(&format!(
+            "{:<10} {:>7} {:>8} {:>8.1}% {:>6}  {}\n",
+            frag.to_string().to_ascii_lowercase(),
+            count,
+            coverage,
+            *coverage as f64 * 100.0 / stats.bytes.max(1) as f64,
+            max_depth,
+            kinds_str
+        ));
+    }
+
+    res
+}
@@ -0,0 +1,228 @@
+//! Colors which the terminal can show.
+//!
+//! Unless `--color-depth` is given, it is detected from the environment:
+//!
+//! - `NO_COLOR` ( non-empty ) or `CLICOLOR=0` : no colors
+//! - not a terminal : no colors, unless `CLICOLOR_FORCE` is set to other than `0`
+//! - `TERM=dumb` : no colors
+//! - `COLORTERM=truecolor` or `COLORTERM=24bit` : 24-bit RGB
+//! - `TERM=*256color*` : 256 colors
+//! - otherwise : 16 colors
+
+use crate::theme::{ansi256_to_rgb, rgb_to_ansi16, rgb_to_ansi256, BgFg, ThemeColor};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use console::{Color, Term};
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+static COLOR_DEPTH: OnceLock<ColorDepth> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum ColorDepth {
+    /// Plain text
+    None,
+    /// The 16 ANSI colors
+    #[value(name = "16")]
+    Ansi16,
+    /// The 256 colors of xterm
+    #[value(name = "256")]
+    Ansi256,
+    /// 24-bit RGB
+    #[value(name = "truecolor")]
+    TrueColor,
+}
+
+/// The color depth given by [`init`] , or the detected one.
+pub fn color_depth() -> ColorDepth {
+    *COLOR_DEPTH.get_or_init(detect)
+}
+
+/// Sets the color depth of stdout to `depth` , or to the detected one.
+pub fn init(depth: Option<ColorDepth>) -> Result<()> {
+    if env_is_set("NO_COLOR") {
+        console::set_colors_enabled_stderr(false);
+    }
+
+    let depth = depth.unwrap_or_else(detect);
+    // other styles on stdout, e.g. error markers of `--recover` , follow the same setting
+    console::set_colors_enabled(depth != ColorDepth::None);
+
+    COLOR_DEPTH
+        .set(depth)
+        .map_err(|_| anyhow!("the color depth is already set"))
+}
+
+fn detect() -> ColorDepth {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+
+    if env_is_set("NO_COLOR") || var("CLICOLOR") == "0" {
+        return ColorDepth::None;
+    }
+
+    let forced = !matches!(var("CLICOLOR_FORCE").as_str(), "" | "0");
+    if !forced && !Term::stdout().features().colors_supported() {
+        return ColorDepth::None;
+    }
+
+    let term = var("TERM");
+    let colorterm = var("COLORTERM").to_ascii_lowercase();
+
+    if term == "dumb" {
+        ColorDepth::None
+    } else if colorterm == "truecolor" || colorterm == "24bit" {
+        ColorDepth::TrueColor
+    } else if term.contains("256color") {
+        ColorDepth::Ansi256
+    } else {
+        ColorDepth::Ansi16
+    }
+}
+
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+/// Whether the background of the terminal is light, by `COLORFGBG` , e.g. `0;15` .
+/// `None` if unknown.
+pub fn is_light_background() -> Option<bool> {
+    let colorfgbg = std::env::var("COLORFGBG").ok()?;
+    let bg = colorfgbg.rsplit(';').next()?.parse::<u8>().ok()?;
+
+    Some(matches!(bg, 7 | 9..=15))
+}
+
+/// Text style written with escape sequences for [`color_depth`] .
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TermStyle {
+    fg: Option<ThemeColor>,
+    bg: Option<ThemeColor>,
+    bold: bool,
+    underline: bool,
+    italic: bool,
+}
+
+impl TermStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fg(self, color: ThemeColor) -> Self {
+        Self {
+            fg: Some(color),
+            ..self
+        }
+    }
+
+    pub fn bg(self, color: ThemeColor) -> Self {
+        Self {
+            bg: Some(color),
+            ..self
+        }
+    }
+
+    pub fn bold(self) -> Self {
+        Self { bold: true, ..self }
+    }
+
+    pub fn underlined(self) -> Self {
+        Self {
+            underline: true,
+            ..self
+        }
+    }
+
+    pub fn italic(self) -> Self {
+        Self {
+            italic: true,
+            ..self
+        }
+    }
+
+    pub fn apply_to(&self, text: impl Display) -> String {
+        let depth = color_depth();
+        if depth == ColorDepth::None {
+            return text.to_string();
+        }
+
+        let mut codes = Vec::new();
+        if let Some(color) = self.fg {
+            codes.push(sgr_color(color, BgFg::Fg, depth));
+        }
+        if let Some(color) = self.bg {
+            codes.push(sgr_color(color, BgFg::Bg, depth));
+        }
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.italic {
+            codes.push("3".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+
+        if codes.is_empty() {
+            return text.to_string();
+        }
+
+        format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+    }
+}
+
+/// SGR parameters of `color` , reduced to the nearest one `depth` has.
+fn sgr_color(color: ThemeColor, target: BgFg, depth: ColorDepth) -> String {
+    let (base, bright_base, extended) = match target {
+        BgFg::Fg => (30, 90, 38),
+        BgFg::Bg => (40, 100, 48),
+    };
+
+    let basic = |n: u8| {
+        if n < 8 {
+            (base + n).to_string()
+        } else {
+            (bright_base + n - 8).to_string()
+        }
+    };
+
+    let color = match color {
+        ThemeColor::Ansi(color) => match ansi_index(color) {
+            Ok(n) => return basic(n),
+            Err(n) => ThemeColor::Ansi256(n),
+        },
+        color => color,
+    };
+
+    match (color, depth) {
+        (ThemeColor::Rgb(r, g, b), ColorDepth::TrueColor) => {
+            format!("{};2;{};{};{}", extended, r, g, b)
+        }
+        (ThemeColor::Rgb(r, g, b), ColorDepth::Ansi256) => {
+            format!("{};5;{}", extended, rgb_to_ansi256((r, g, b)))
+        }
+        (ThemeColor::Ansi256(n), ColorDepth::Ansi256 | ColorDepth::TrueColor) => {
+            format!("{};5;{}", extended, n)
+        }
+        (ThemeColor::Ansi256(n), _) if n < 16 => basic(n),
+        (ThemeColor::Ansi256(n), _) => basic(rgb_to_ansi16(ansi256_to_rgb(n))),
+        (ThemeColor::Rgb(r, g, b), _) => basic(rgb_to_ansi16((r, g, b))),
+        (ThemeColor::Ansi(_), _) => unreachable!(),
+    }
+}
+
+/// Index of one of the 8 colors, or of the 256 colors for `Color256` .
+fn ansi_index(color: Color) -> Result<u8, u8> {
+    let n = match color {
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::White => 7,
+        Color::Color256(n) => return Err(n),
+    };
+
+    Ok(n)
+}
@@ -0,0 +1,538 @@
+//! Colors of fragments, which can be changed by a theme file.
+//!
+//! ```toml
+//! # ~/.config/coloring_rust/theme.toml
+//! preset = "dark"           # base of the theme: "classic", "dark" or "light"
+//!
+//! [expr]
+//! color = "#00ced1"         # "#rrggbb", a 256-color index like 44, or an ANSI color name like "cyan"
+//! target = "bg"             # "fg" (default) or "bg"
+//! bold = true
+//! underline = false
+//! italic = false
+//! ```
+//!
+//! Tables are named by fragment specifiers. Omitted fields are taken from the preset.
+//! Without a preset, "classic" is used on terminals with 16 colors or less,
+//! and the colors of the web version otherwise.
+//!
+//! `priority = ["item", "block", ...]` at the top sets the drawing order
+//! in the same way as `--priority` .
+
+use crate::terminal::{self, ColorDepth, TermStyle};
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use coloring_common::FragSpecs;
+use console::Color;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// The theme given by [`init`] , or the preset for the terminal.
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(|| Theme::preset(Preset::detect()))
+}
+
+/// Loads the theme from `path` , or from the config directory if it has one.
+/// `preset` and `priority` take precedence over the ones in the file.
+pub fn init(
+    path: Option<PathBuf>,
+    preset: Option<Preset>,
+    priority: Option<Vec<FragSpecs>>,
+) -> Result<()> {
+    let path = path.or_else(|| default_path().filter(|path| path.exists()));
+
+    let mut theme = match path {
+        Some(path) => Theme::load(&path, preset)?,
+        None => Theme::preset(preset.unwrap_or_else(Preset::detect)),
+    };
+
+    if let Some(priority) = priority {
+        theme.priority = resolve_priority(&priority)?;
+    }
+
+    THEME
+        .set(theme)
+        .map_err(|_| anyhow!("the theme is already set"))
+}
+
+/// `$XDG_CONFIG_HOME/coloring_rust/theme.toml` or `~/.config/coloring_rust/theme.toml`
+fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_dir.join("coloring_rust").join("theme.toml"))
+}
+
+/// Built-in themes.
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// The 8 ANSI colors
+    Classic,
+    /// Same colors as the dark mode of the web version
+    Dark,
+    /// Same colors as the light mode of the web version
+    Light,
+}
+
+impl Preset {
+    /// Classic for 16 colors or less, since the web colors are hard to tell apart in them.
+    /// Otherwise the web colors for the background given by `COLORFGBG` , dark by default.
+    fn detect() -> Self {
+        if terminal::color_depth() < ColorDepth::Ansi256 {
+            return Preset::Classic;
+        }
+
+        match terminal::is_light_background() {
+            Some(true) => Preset::Light,
+            _ => Preset::Dark,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    styles: HashMap<FragSpecs, CustomStyle>,
+    /// Drawing order of highlights. A later one is drawn over earlier ones.
+    priority: Vec<FragSpecs>,
+}
+
+impl Theme {
+    pub fn style(&self, frag: FragSpecs) -> CustomStyle {
+        self.styles[&frag]
+    }
+
+    pub fn priority(&self) -> &[FragSpecs] {
+        &self.priority
+    }
+
+    pub fn preset(preset: Preset) -> Self {
+        let styles = match preset {
+            Preset::Classic => classic(),
+            Preset::Dark => web_palette(true),
+            Preset::Light => web_palette(false),
+        };
+
+        Self {
+            styles: styles.into_iter().collect(),
+            priority: FragSpecs::DEFAULT_PRIORITY.to_vec(),
+        }
+    }
+
+    fn load(path: &Path, preset: Option<Preset>) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read the theme {}", path.display()))?;
+
+        Self::parse(&text, preset)
+            .with_context(|| format!("failed to parse the theme {}", path.display()))
+    }
+
+    /// `preset` takes precedence over the one in `text` .
+    fn parse(text: &str, preset: Option<Preset>) -> Result<Self> {
+        let file: ThemeFile = toml::from_str(text)?;
+
+        let mut theme = Self::preset(preset.or(file.preset).unwrap_or_else(Preset::detect));
+
+        if let Some(priority) = file.priority {
+            theme.priority = resolve_priority(&priority).context("invalid priority")?;
+        }
+
+        for (frag, fields) in file.frags {
+            let style = theme.styles.get_mut(&frag).unwrap();
+            *style = fields.apply(*style);
+        }
+
+        Ok(theme)
+    }
+}
+
+#[derive(Deserialize)]
+struct ThemeFile {
+    preset: Option<Preset>,
+    priority: Option<Vec<FragSpecs>>,
+    #[serde(flatten)]
+    frags: HashMap<FragSpecs, StyleFields>,
+}
+
+/// `listed` from the lowest to the highest. The others are drawn below them in the default order.
+fn resolve_priority(listed: &[FragSpecs]) -> Result<Vec<FragSpecs>> {
+    for (i, frag) in listed.iter().enumerate() {
+        if listed[..i].contains(frag) {
+            return Err(anyhow!(
+                "`{}` is listed twice in the priority",
+                frag.to_string().to_ascii_lowercase()
+            ));
+        }
+    }
+
+    Ok(FragSpecs::DEFAULT_PRIORITY
+        .into_iter()
+        .filter(|frag| !listed.contains(frag))
+        .chain(listed.iter().copied())
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleFields {
+    color: Option<ThemeColor>,
+    target: Option<BgFg>,
+    bold: Option<bool>,
+    underline: Option<bool>,
+    italic: Option<bool>,
+}
+
+impl StyleFields {
+    fn apply(self, style: CustomStyle) -> CustomStyle {
+        CustomStyle {
+            color: self.color.unwrap_or(style.color),
+            bgfg: self.target.unwrap_or(style.bgfg),
+            bold: self.bold.unwrap_or(style.bold),
+            underline: self.underline.unwrap_or(style.underline),
+            italic: self.italic.unwrap_or(style.italic),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BgFg {
+    Bg,
+    Fg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawColor")]
+pub enum ThemeColor {
+    Ansi(Color),
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawColor {
+    Index(u8),
+    Name(String),
+}
+
+impl TryFrom<RawColor> for ThemeColor {
+    type Error = String;
+
+    fn try_from(raw: RawColor) -> Result<Self, Self::Error> {
+        let name = match raw {
+            RawColor::Index(n) => return Ok(ThemeColor::Ansi256(n)),
+            RawColor::Name(name) => name,
+        };
+
+        if let Some(hex) = name.strip_prefix('#') {
+            let channel = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+            };
+
+            return match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Some(r), Some(g), Some(b)) => Ok(ThemeColor::Rgb(r, g, b)),
+                _ => Err(format!("`{}` is not a `#rrggbb` color", name)),
+            };
+        }
+
+        let color = match name.to_ascii_lowercase().as_str() {
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "white" => Color::White,
+            _ => return Err(format!("unknown color `{}`", name)),
+        };
+
+        Ok(ThemeColor::Ansi(color))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CustomStyle {
+    pub color: ThemeColor,
+    pub bgfg: BgFg,
+    pub bold: bool,
+    pub underline: bool,
+    pub italic: bool,
+}
+
+impl CustomStyle {
+    fn new(color: ThemeColor) -> Self {
+        Self {
+            color,
+            bgfg: BgFg::Fg,
+            bold: false,
+            underline: false,
+            italic: false,
+        }
+    }
+
+    fn set_bgfg(self, bgfg: BgFg) -> Self {
+        Self { bgfg, ..self }
+    }
+
+    pub fn to_style(self) -> TermStyle {
+        let mut style = match self.bgfg {
+            BgFg::Bg => TermStyle::new().bg(self.color),
+            BgFg::Fg => TermStyle::new().fg(self.color),
+        };
+
+        if self.bold {
+            style = style.bold();
+        }
+        if self.underline {
+            style = style.underlined();
+        }
+        if self.italic {
+            style = style.italic();
+        }
+
+        style
+    }
+}
+
+fn classic() -> [(FragSpecs, CustomStyle); 14] {
+    let ansi = |color| CustomStyle::new(ThemeColor::Ansi(color));
+
+    [
+        (FragSpecs::Block, ansi(Color::Blue)),
+        (FragSpecs::Expr, ansi(Color::Blue).set_bgfg(BgFg::Bg)),
+        (FragSpecs::Expr2021, ansi(Color::Blue).set_bgfg(BgFg::Bg)),
+        (FragSpecs::Ident, ansi(Color::Yellow).set_bgfg(BgFg::Bg)),
+        (FragSpecs::Item, ansi(Color::Yellow)),
+        (FragSpecs::Lifetime, ansi(Color::Red).set_bgfg(BgFg::Bg)),
+        (FragSpecs::Literal, ansi(Color::Magenta).set_bgfg(BgFg::Bg)),
+        (FragSpecs::Meta, ansi(Color::Magenta)),
+        (FragSpecs::Pat, ansi(Color::Green).set_bgfg(BgFg::Bg)),
+        (FragSpecs::PatParam, ansi(Color::Cyan)),
+        (FragSpecs::Path, ansi(Color::Green)),
+        (FragSpecs::Stmt, ansi(Color::Cyan).set_bgfg(BgFg::Bg)),
+        (FragSpecs::Ty, ansi(Color::Yellow).set_bgfg(BgFg::Bg)),
+        (FragSpecs::Vis, ansi(Color::Yellow)),
+    ]
+}
+
+/// ref: `initHighlightTargetArray` of coloring_web
+fn web_palette(is_dark_mode: bool) -> [(FragSpecs, CustomStyle); 14] {
+    let rgb = |(r, g, b)| CustomStyle::new(ThemeColor::Rgb(r, g, b));
+    // highlights are drawn as backgrounds in the light mode
+    let highlight = |color| {
+        let bgfg = if is_dark_mode { BgFg::Fg } else { BgFg::Bg };
+        rgb(color).set_bgfg(bgfg)
+    };
+
+    let (block_color, item_color) = if is_dark_mode {
+        // lightblue, white
+        ((173, 216, 230), (255, 255, 255))
+    } else {
+        // darkcyan, darkblue
+        ((0, 139, 139), (0, 0, 139))
+    };
+
+    [
+        (FragSpecs::Literal, highlight((240, 128, 128))), // lightcoral
+        (FragSpecs::Ident, highlight((255, 165, 0))),     // orange
+        (FragSpecs::Path, highlight((0, 255, 0))),        // lime
+        (FragSpecs::Pat, highlight((144, 238, 144))),     // lightgreen
+        (FragSpecs::PatParam, highlight((60, 179, 113))), // mediumseagreen
+        (FragSpecs::Ty, highlight((0, 128, 0))),          // green
+        (FragSpecs::Lifetime, highlight((127, 255, 212))), // aquamarine
+        (FragSpecs::Vis, highlight((255, 192, 203))),     // pink
+        (FragSpecs::Expr, highlight((0, 255, 255))),      // cyan
+        (FragSpecs::Expr2021, highlight((0, 206, 209))),  // darkturquoise
+        (FragSpecs::Stmt, highlight((135, 206, 250))),    // lightskyblue
+        (FragSpecs::Meta, highlight((238, 130, 238))),    // violet
+        (FragSpecs::Block, rgb(block_color)),
+        (FragSpecs::Item, rgb(item_color)),
+    ]
+}
+
+// ref: https://en.wikipedia.org/wiki/ANSI_escape_code#8-bit
+pub fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (128, 0, 0),
+        (0, 128, 0),
+        (128, 128, 0),
+        (0, 0, 128),
+        (128, 0, 128),
+        (0, 128, 128),
+        (192, 192, 192),
+        (128, 128, 128),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (0, 0, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+
+    match n {
+        0..=15 => BASIC[n as usize],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        232..=255 => {
+            let v = 8 + (n - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+/// The nearest one in the color cube and the grayscale ramp. The basic 16 colors are not used
+/// since terminals render them differently.
+pub fn rgb_to_ansi256(rgb: (u8, u8, u8)) -> u8 {
+    nearest(rgb, 16..=255)
+}
+
+/// The nearest one of the basic 16 colors, for terminals without the others.
+pub fn rgb_to_ansi16(rgb: (u8, u8, u8)) -> u8 {
+    nearest(rgb, 0..=15)
+}
+
+fn nearest((r, g, b): (u8, u8, u8), candidates: RangeInclusive<u8>) -> u8 {
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        [(r, r2), (g, g2), (b, b2)]
+            .iter()
+            .map(|&(a, b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+
+    candidates
+        .min_by_key(|&n| distance(ansi256_to_rgb(n)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_override_the_preset() {
+        let theme = Theme::parse(
+            r##"
+            preset = "light"
+
+            [expr]
+            color = "#00ced1"
+            target = "fg"
+            bold = true
+
+            [pat_param]
+            color = 44
+
+            [ty]
+            color = "Cyan"
+            italic = true
+            "##,
+            None,
+        )
+        .unwrap();
+
+        let expr = theme.style(FragSpecs::Expr);
+        assert_eq!(expr.color, ThemeColor::Rgb(0, 206, 209));
+        assert!(matches!(expr.bgfg, BgFg::Fg));
+        assert!(expr.bold && !expr.underline && !expr.italic);
+
+        assert_eq!(
+            theme.style(FragSpecs::PatParam).color,
+            ThemeColor::Ansi256(44)
+        );
+
+        let ty = theme.style(FragSpecs::Ty);
+        assert_eq!(ty.color, ThemeColor::Ansi(Color::Cyan));
+        // the target of the light preset is kept
+        assert!(matches!(ty.bgfg, BgFg::Bg));
+        assert!(ty.italic);
+
+        let light = Theme::preset(Preset::Light);
+        assert_eq!(
+            theme.style(FragSpecs::Ident).color,
+            light.style(FragSpecs::Ident).color
+        );
+        assert_eq!(theme.priority(), FragSpecs::DEFAULT_PRIORITY);
+    }
+
+    #[test]
+    fn given_preset_wins() {
+        let theme = Theme::parse(r#"preset = "light""#, Some(Preset::Classic)).unwrap();
+
+        assert_eq!(
+            theme.style(FragSpecs::Item).color,
+            ThemeColor::Ansi(Color::Yellow)
+        );
+    }
+
+    #[test]
+    fn invalid_themes() {
+        for text in [
+            "[expr]\ncolor = \"#12345\"",
+            "[expr]\ncolor = \"#gg0000\"",
+            "[expr]\ncolor = \"purple\"",
+            "[expr]\ncolor = 256",
+            "[expr]\ntarget = \"both\"",
+            "[expr]\nsize = 1",
+            "[tt]\nbold = true",
+            "preset = \"solarized\"",
+            "priority = [\"expr\", \"expr\"]",
+        ] {
+            assert!(
+                Theme::parse(text, Some(Preset::Dark)).is_err(),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn priority_in_the_file() {
+        let theme = Theme::parse(r#"priority = ["literal", "item"]"#, Some(Preset::Dark)).unwrap();
+
+        assert_eq!(theme.priority().first(), Some(&FragSpecs::Block));
+        assert_eq!(
+            theme.priority()[theme.priority().len() - 2..],
+            [FragSpecs::Literal, FragSpecs::Item]
+        );
+    }
+
+    #[test]
+    fn listed_ones_are_drawn_last() {
+        let priority = resolve_priority(&[FragSpecs::Item, FragSpecs::Block]).unwrap();
+
+        assert_eq!(priority.len(), FragSpecs::DEFAULT_PRIORITY.len());
+        assert_eq!(
+            priority[..3],
+            [FragSpecs::Meta, FragSpecs::Stmt, FragSpecs::Expr2021]
+        );
+        assert_eq!(priority[12..], [FragSpecs::Item, FragSpecs::Block]);
+
+        assert_eq!(resolve_priority(&[]).unwrap(), FragSpecs::DEFAULT_PRIORITY);
+    }
+
+    #[test]
+    fn listed_twice() {
+        let error = resolve_priority(&[FragSpecs::PatParam, FragSpecs::Ty, FragSpecs::PatParam])
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "`pat_param` is listed twice in the priority"
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{FragRange, MatchError, Utf16Index, WholeMatcher};
+use proc_macro2::LineColumn;
+use serde::Serialize;
+
+/// A parse error of a matcher, located in the source.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub matcher: WholeMatcher,
+    pub message: String,
+    pub range: FragRange,
+}
+
+/// Locates every error of `errors` in `content` . A `syn::Error` may carry several messages,
+/// each of which becomes a diagnostic.
+pub fn diagnostics(content: &str, errors: &[MatchError]) -> Vec<Diagnostic> {
+    let utf16 = Utf16Index::new(content);
+
+    errors
+        .iter()
+        .flat_map(|MatchError { matcher, error }| {
+            error.into_iter().map(|error| Diagnostic {
+                matcher: *matcher,
+                message: error.to_string(),
+                range: locate(content, &error, &utf16),
+            })
+        })
+        .collect()
+}
+
+fn locate(content: &str, error: &syn::Error, utf16: &Utf16Index) -> FragRange {
+    let range = FragRange::new(error.span(), utf16);
+
+    // syn reports the end of input at the call site, which is the very beginning
+    if !range.byte.is_empty() || !error.to_string().starts_with("unexpected end of input") {
+        return range;
+    }
+
+    let end = content.trim_end().len();
+    let position = line_column(content, end);
+
+    FragRange {
+        byte: end..end,
+        start: position,
+        end: position,
+        utf16: utf16.offset(end)..utf16.offset(end),
+    }
+}
+
+fn line_column(content: &str, byte: usize) -> LineColumn {
+    let before = &content[..byte];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    LineColumn {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count(),
+    }
+}
@@ -0,0 +1,92 @@
+use clap::ValueEnum;
+use proc_macro2::{Group, Ident, TokenStream, TokenTree};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+#[derive(
+    PartialEq,
+    Eq,
+    Hash,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    ValueEnum,
+    Serialize,
+    Deserialize,
+    Display,
+    PartialOrd,
+    Ord,
+)]
+pub enum Edition {
+    #[serde(rename = "2015")]
+    #[value(name = "2015")]
+    #[strum(to_string = "2015")]
+    E2015,
+    #[serde(rename = "2018")]
+    #[value(name = "2018")]
+    #[strum(to_string = "2018")]
+    E2018,
+    #[default]
+    #[serde(rename = "2021")]
+    #[value(name = "2021")]
+    #[strum(to_string = "2021")]
+    E2021,
+    #[serde(rename = "2024")]
+    #[value(name = "2024")]
+    #[strum(to_string = "2024")]
+    E2024,
+}
+
+impl Edition {
+    /// `pat` accepts top-level or-patterns.
+    pub fn has_or_patterns(self) -> bool {
+        self >= Edition::E2021
+    }
+
+    /// `expr` accepts `const {}` blocks and `_`.
+    pub fn has_expr_2024(self) -> bool {
+        self >= Edition::E2024
+    }
+
+    /// Lexes `content` as this edition does.
+    ///
+    /// syn always treats `async`, `await` and `try` as keywords,
+    /// so they are turned into raw identifiers for 2015.
+    /// `dyn` is left as it is since it is a weak keyword in 2015.
+    pub fn tokenize(self, content: &str) -> syn::Result<TokenStream> {
+        let tokens = syn::parse_str::<TokenStream>(content)?;
+
+        self.fix_keywords(tokens)
+    }
+
+    fn fix_keywords(self, tokens: TokenStream) -> syn::Result<TokenStream> {
+        tokens
+            .into_iter()
+            .map(|tree| match tree {
+                TokenTree::Group(group) => {
+                    let mut new_group =
+                        Group::new(group.delimiter(), self.fix_keywords(group.stream())?);
+                    new_group.set_span(group.span());
+
+                    Ok(TokenTree::Group(new_group))
+                }
+                TokenTree::Ident(ident) => {
+                    let name = ident.to_string();
+
+                    match (self, name.as_str()) {
+                        (Edition::E2015, "async" | "await" | "try") => {
+                            Ok(TokenTree::Ident(Ident::new_raw(&name, ident.span())))
+                        }
+                        (Edition::E2024, "gen") => Err(syn::Error::new(
+                            ident.span(),
+                            "`gen` is a reserved keyword since edition 2024",
+                        )),
+                        _ => Ok(TokenTree::Ident(ident)),
+                    }
+                }
+                tree => Ok(tree),
+            })
+            .collect()
+    }
+}
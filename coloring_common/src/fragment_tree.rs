@@ -0,0 +1,179 @@
+use crate::{FragRange, FragSpecs};
+use serde::Serialize;
+
+/// A syntax node which some fragment specifiers accept, with the nodes inside it.
+#[derive(Debug, Clone, Serialize)]
+pub struct FragNode {
+    /// Never empty.
+    pub frags: Vec<FragSpecs>,
+    /// Name of the concrete syn type, e.g. `ExprCall` or `TypeReference` .
+    pub kind: &'static str,
+    pub range: FragRange,
+    pub children: Vec<FragNode>,
+}
+
+impl FragNode {
+    /// Pre-order traversal.
+    pub fn walk(&self, f: &mut impl FnMut(&FragNode, usize)) {
+        self.walk_inner(f, 0);
+    }
+
+    /// Keeps only `frags` which satisfy `f` .
+    /// A node losing all of them is replaced by its children.
+    pub fn filter(mut self, f: &impl Fn(FragSpecs) -> bool) -> Vec<FragNode> {
+        self.frags.retain(|frag| f(*frag));
+
+        let children = self
+            .children
+            .into_iter()
+            .flat_map(|child| child.filter(f))
+            .collect();
+
+        if self.frags.is_empty() {
+            children
+        } else {
+            self.children = children;
+            vec![self]
+        }
+    }
+
+    fn walk_inner(&self, f: &mut impl FnMut(&FragNode, usize), depth: usize) {
+        f(self, depth);
+
+        for child in &self.children {
+            child.walk_inner(f, depth + 1);
+        }
+    }
+}
+
+/// Syntax node whose concrete kind is reported in [`FragNode::kind`] .
+pub(crate) trait NodeKind {
+    fn node_kind(&self) -> &'static str;
+}
+
+macro_rules! node_kind {
+    ($ty:ident, [$($variant:ident),* $(,)?]) => {
+        impl NodeKind for syn::$ty {
+            fn node_kind(&self) -> &'static str {
+                match self {
+                    $(syn::$ty::$variant(..) => concat!(stringify!($ty), stringify!($variant)),)*
+                    _ => stringify!($ty),
+                }
+            }
+        }
+    };
+    ($ty:ident) => {
+        impl NodeKind for syn::$ty {
+            fn node_kind(&self) -> &'static str {
+                stringify!($ty)
+            }
+        }
+    };
+}
+
+node_kind!(
+    Expr,
+    [
+        Array, Assign, Async, Await, Binary, Block, Break, Call, Cast, Closure, Const, Continue,
+        Field, ForLoop, Group, If, Index, Infer, Let, Lit, Loop, Macro, Match, MethodCall, Paren,
+        Path, Range, RawAddr, Reference, Repeat, Return, Struct, Try, TryBlock, Tuple, Unary,
+        Unsafe, While, Yield,
+    ]
+);
+node_kind!(
+    Pat,
+    [
+        Const,
+        Ident,
+        Lit,
+        Macro,
+        Or,
+        Paren,
+        Path,
+        Range,
+        Reference,
+        Rest,
+        Slice,
+        Struct,
+        Tuple,
+        TupleStruct,
+        Type,
+        Wild,
+    ]
+);
+node_kind!(
+    Type,
+    [
+        Array,
+        BareFn,
+        Group,
+        ImplTrait,
+        Infer,
+        Macro,
+        Never,
+        Paren,
+        Path,
+        Ptr,
+        Reference,
+        Slice,
+        TraitObject,
+        Tuple,
+    ]
+);
+node_kind!(
+    Item,
+    [
+        Const,
+        Enum,
+        ExternCrate,
+        Fn,
+        ForeignMod,
+        Impl,
+        Macro,
+        Mod,
+        Static,
+        Struct,
+        Trait,
+        TraitAlias,
+        Type,
+        Union,
+        Use,
+    ]
+);
+node_kind!(Lit, [Str, ByteStr, CStr, Byte, Char, Int, Float, Bool]);
+node_kind!(File);
+node_kind!(Block);
+node_kind!(Ident);
+node_kind!(Lifetime);
+node_kind!(Path);
+
+impl NodeKind for syn::Meta {
+    fn node_kind(&self) -> &'static str {
+        match self {
+            syn::Meta::Path(_) => "Path",
+            syn::Meta::List(_) => "MetaList",
+            syn::Meta::NameValue(_) => "MetaNameValue",
+        }
+    }
+}
+
+impl NodeKind for syn::Stmt {
+    fn node_kind(&self) -> &'static str {
+        match self {
+            syn::Stmt::Local(_) => "Local",
+            syn::Stmt::Item(item) => item.node_kind(),
+            syn::Stmt::Expr(expr, _) => expr.node_kind(),
+            syn::Stmt::Macro(_) => "StmtMacro",
+        }
+    }
+}
+
+impl NodeKind for syn::Visibility {
+    fn node_kind(&self) -> &'static str {
+        match self {
+            syn::Visibility::Public(_) => "VisPublic",
+            syn::Visibility::Restricted(_) => "VisRestricted",
+            syn::Visibility::Inherited => "VisInherited",
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{FragSpecs, Piece, RangeMap, Recovered};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use std::cmp::Ordering;
+use std::ops::Range;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum SpanTag {
+    Start(FragSpecs, usize),
+    End,
+}
+
+impl PartialOrd for SpanTag {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SpanTag {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // End < Start
+        // Start 2 < Start 1
+        match (self, other) {
+            (SpanTag::Start(_, _), SpanTag::End) => Ordering::Greater,
+            (SpanTag::End, SpanTag::Start(_, _)) => Ordering::Less,
+            (SpanTag::Start(_, len_self), SpanTag::Start(_, len_other)) => len_other.cmp(len_self),
+            (SpanTag::End, SpanTag::End) => Ordering::Equal,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum SpanTagForRender {
+    Start(FragSpecs, Vec<FragSpecs>),
+    End,
+}
+
+fn span_tag_map(len: usize, ranges: RangeMap) -> Vec<Vec<SpanTagForRender>> {
+    let mut tags_all: Vec<Vec<SpanTag>> = (0..len + 1).map(|_| Vec::new()).collect();
+
+    for (frag, ranges) in ranges.into_iter() {
+        for Range { start, end } in ranges {
+            if end == 0 || end > len {
+                // invalid range
+                continue;
+            }
+
+            tags_all[start].push(SpanTag::Start(frag, end - start));
+            tags_all[end].push(SpanTag::End);
+        }
+    }
+
+    for tags in tags_all.iter_mut() {
+        tags.sort(); // </span> </span> <span class="..."> <span class="...">
+    }
+
+    let mut stack = Vec::new();
+    let mut tags_for_render_all: Vec<Vec<SpanTagForRender>> =
+        (0..len + 1).map(|_| Vec::new()).collect();
+    for (i, tags_for_render) in tags_for_render_all.iter_mut().enumerate() {
+        for tag in tags_all[i].iter() {
+            match tag {
+                SpanTag::End => {
+                    stack.pop();
+
+                    tags_for_render.push(SpanTagForRender::End);
+                }
+                SpanTag::Start(frag, _) => {
+                    stack.push(*frag);
+
+                    tags_for_render.push(SpanTagForRender::Start(*frag, stack.clone()));
+                }
+            }
+        }
+    }
+
+    tags_for_render_all
+}
+
+/// Renders `content` with nested `<span data-frag="..." class="...">` tags for `ranges` .
+///
+/// `data-frag` is the fragment of the span itself and `class` lists it and all its ancestors.
+pub fn to_html_string(content: &str, ranges: RangeMap) -> String {
+    let span_tag_map = span_tag_map(content.len(), ranges);
+
+    let mut res: String = content
+        .char_indices()
+        .map(|(i, c)| {
+            let mut res = String::new();
+
+            for tag in &span_tag_map[i] {
+                let tag = match tag {
+                    SpanTagForRender::End => "</span>".to_string(),
+                    SpanTagForRender::Start(ref frag, ref frags) => {
+                        let frags = frags
+                            .iter()
+                            .map(|f| f.to_string().to_ascii_lowercase())
+                            .collect::<Vec<_>>()
+                            .join(" ");
+
+                        format!(
+                            "<span data-frag=\"{}\" class=\"{}\">",
+                            frag.to_string().to_ascii_lowercase(),
+                            frags
+                        )
+                    }
+                };
+                res.push_str(&tag);
+            }
+
+            res.push_str(&encode_text(&c.to_string()));
+
+            res
+        })
+        .collect();
+
+    for tag in span_tag_map.last().unwrap() {
+        if let &SpanTagForRender::End = tag {
+            res.push_str("</span>");
+        }
+    }
+
+    res
+}
+
+/// Renders the result of [`crate::recover`] .
+/// Broken pieces are wrapped with `<span class="parse-error" title="...">`
+/// and followed by `<span class="parse-error-message">` having the message.
+pub fn recovered_to_html_string(content: &str, recovered: &Recovered) -> String {
+    let mut res = String::new();
+    let mut pos = 0;
+
+    for piece in &recovered.pieces {
+        let range = piece.range().clone();
+        res.push_str(&encode_text(&content[pos..range.start]));
+
+        match piece {
+            Piece::Parsed { ranges, .. } => {
+                let ranges = ranges
+                    .iter()
+                    .map(|(frag, ranges)| {
+                        let ranges = ranges
+                            .iter()
+                            .map(|r| r.byte.start - range.start..r.byte.end - range.start)
+                            .collect();
+
+                        (*frag, ranges)
+                    })
+                    .collect();
+
+                res.push_str(&to_html_string(&content[range.clone()], ranges));
+            }
+            Piece::Failed { message, .. } => {
+                res.push_str(&format!(
+                    "<span class=\"parse-error\" title=\"{}\">{}</span><span class=\"parse-error-message\">{}</span>",
+                    encode_double_quoted_attribute(message),
+                    encode_text(&content[range.clone()]),
+                    encode_text(message),
+                ));
+            }
+        }
+
+        pos = range.end;
+    }
+
+    res.push_str(&encode_text(&content[pos..]));
+
+    res
+}
@@ -1,12 +1,42 @@
 use clap::ValueEnum;
+use fragment_tree::NodeKind;
+use proc_macro2::Span;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::ops::Range;
 use strum::Display;
 use syn::spanned::Spanned;
 use syn::visit::Visit;
 
+mod diagnostic;
+mod edition;
+mod fragment_tree;
+mod html;
+pub mod mbe;
+mod outline;
+mod position;
+mod query;
+mod recovery;
+mod selection;
+mod stats;
+mod whole_matcher;
+mod why_not;
+
+pub use diagnostic::{diagnostics, Diagnostic};
+pub use edition::Edition;
+pub use fragment_tree::FragNode;
+pub use html::{recovered_to_html_string, to_html_string};
+pub use outline::outline;
+pub use position::{FragRange, FragRangeMap, Utf16Index};
+pub use query::{fragments_at, stack_at, FragmentAt, FragmentsAt, Offset};
+pub use recovery::{recover, Piece, Recovered};
+pub use selection::{classify_selection, SelectionClassification};
+pub use stats::{stats, FragStats, Stats};
+pub use whole_matcher::{
+    classify, matcher_name, Classification, Fragments, MatchError, RangeMap, TopMatch, WholeMatcher,
+};
+pub use why_not::{why_not, WhyNot};
+
 // ref: https://veykril.github.io/tlborm/decl-macros/minutiae/fragment-specifiers.html
 #[derive(
     PartialEq,
@@ -26,12 +56,20 @@ use syn::visit::Visit;
 pub enum FragSpecs {
     Block,
     Expr,
+    #[serde(rename = "expr_2021")]
+    #[value(name = "expr_2021")]
+    #[strum(to_string = "expr_2021")]
+    Expr2021,
     Ident,
     Item,
     Lifetime,
     Literal,
     Meta,
     Pat,
+    #[serde(rename = "pat_param")]
+    #[value(name = "pat_param")]
+    #[strum(to_string = "pat_param")]
+    PatParam,
     Path,
     Stmt,
     // TT,
@@ -39,20 +77,64 @@ pub enum FragSpecs {
     Vis,
 }
 
+impl FragSpecs {
+    /// Default drawing order of highlights. A later one is drawn over earlier ones.
+    pub const DEFAULT_PRIORITY: [FragSpecs; 14] = [
+        FragSpecs::Item,
+        FragSpecs::Block,
+        FragSpecs::Meta,
+        FragSpecs::Stmt,
+        FragSpecs::Expr2021,
+        FragSpecs::Expr,
+        FragSpecs::Vis,
+        FragSpecs::Lifetime,
+        FragSpecs::Ty,
+        FragSpecs::PatParam,
+        FragSpecs::Pat,
+        FragSpecs::Path,
+        FragSpecs::Ident,
+        FragSpecs::Literal,
+    ];
+}
+
+/// Decides the fragment drawn at each byte of a content of `len` bytes.
+/// `frags` are drawn in order, so a later one wins. Others are not drawn.
+pub fn paint(len: usize, ranges: &RangeMap, frags: &[FragSpecs]) -> Vec<Option<FragSpecs>> {
+    let mut res = vec![None; len];
+
+    for frag in frags {
+        for range in ranges.get(frag).into_iter().flatten() {
+            let end = range.end.min(len);
+            if range.start < end {
+                res[range.start..end].fill(Some(*frag));
+            }
+        }
+    }
+
+    res
+}
+
 #[derive(Debug)]
 pub struct Colored<T: Debug> {
     pub inner: T,
     pub visit: for<'a> fn(&'a mut Colored<T>, &T),
-    pub ranges: HashMap<FragSpecs, Vec<Range<usize>>>,
+    pub ranges: FragRangeMap,
+    /// Same fragments as `ranges`, nested as in the syntax tree.
+    pub tree: Vec<FragNode>,
+    pub edition: Edition,
+    pub utf16: Utf16Index,
+    stack: Vec<FragNode>,
 }
 
 macro_rules! visit {
     ($elm:ident, $elmty:ty, $key:expr) => {
         fn $elm(&mut self, i: &'ast $elmty) {
-            let ranges = self.ranges.entry($key).or_default();
-            ranges.push(i.span().byte_range());
+            self.enter(i.node_kind(), i.span());
+            self.push_frag($key);
 
             ::syn::visit::$elm(self, i);
+
+            self.leave();
         }
     };
 }
@@ -61,19 +143,70 @@ impl<'ast, T> Visit<'ast> for Colored<T>
 where
     T: Debug,
 {
-    visit!(visit_file, syn::File, FragSpecs::Item);
     visit!(visit_block, syn::Block, FragSpecs::Block);
-    visit!(visit_expr, syn::Expr, FragSpecs::Expr);
+
+    fn visit_expr(&mut self, i: &'ast syn::Expr) {
+        self.enter(i.node_kind(), i.span());
+
+        // `const {}` and `_` are `expr` only since edition 2024, and never `expr_2021`.
+        let is_expr_2024 = matches!(i, syn::Expr::Const(_) | syn::Expr::Infer(_));
+
+        if !is_expr_2024 {
+            self.push_frag(FragSpecs::Expr2021);
+        }
+
+        if !is_expr_2024 || self.edition.has_expr_2024() {
+            self.push_frag(FragSpecs::Expr);
+        }
+
+        ::syn::visit::visit_expr(self, i);
+
+        self.leave();
+    }
+
     visit!(visit_ident, syn::Ident, FragSpecs::Ident);
     visit!(visit_item, syn::Item, FragSpecs::Item);
     visit!(visit_lifetime, syn::Lifetime, FragSpecs::Lifetime);
     visit!(visit_lit, syn::Lit, FragSpecs::Literal);
     visit!(visit_meta, syn::Meta, FragSpecs::Meta);
-    visit!(visit_pat, syn::Pat, FragSpecs::Pat);
+
+    fn visit_pat(&mut self, i: &'ast syn::Pat) {
+        self.enter(i.node_kind(), i.span());
+
+        // `pat_param` is `pat` without top-level or-patterns.
+        // Before edition 2021, `pat` is the same as `pat_param`.
+        let is_or = matches!(i, syn::Pat::Or(_));
+
+        if !is_or {
+            self.push_frag(FragSpecs::PatParam);
+        }
+
+        if !is_or || self.edition.has_or_patterns() {
+            self.push_frag(FragSpecs::Pat);
+        }
+
+        ::syn::visit::visit_pat(self, i);
+
+        self.leave();
+    }
+
     visit!(visit_path, syn::Path, FragSpecs::Path);
     visit!(visit_stmt, syn::Stmt, FragSpecs::Stmt);
     visit!(visit_type, syn::Type, FragSpecs::Ty);
-    visit!(visit_visibility, syn::Visibility, FragSpecs::Vis);
+
+    fn visit_visibility(&mut self, i: &'ast syn::Visibility) {
+        // an omitted visibility has no tokens, and its span is the call site
+        if let syn::Visibility::Inherited = i {
+            return;
+        }
+
+        self.enter(i.node_kind(), i.span());
+        self.push_frag(FragSpecs::Vis);
+
+        ::syn::visit::visit_visibility(self, i);
+
+        self.leave();
+    }
 }
 
 impl<T> Colored<T>
@@ -85,6 +218,63 @@ where
 
         (self.visit)(self, &inner);
     }
+
+    pub fn set_edition(self, edition: Edition) -> Self {
+        Self { edition, ..self }
+    }
+
+    /// Gives the source text, which is needed for UTF-16 offsets of non-ASCII content.
+    pub fn set_source(self, source: &str) -> Self {
+        Self {
+            utf16: Utf16Index::new(source),
+            ..self
+        }
+    }
+
+    /// Byte ranges only.
+    pub fn byte_ranges(&self) -> RangeMap {
+        position::byte_ranges(&self.ranges)
+    }
+}
+
+impl<T> Colored<T>
+where
+    T: Debug,
+{
+    fn enter(&mut self, kind: &'static str, span: Span) {
+        self.stack.push(FragNode {
+            frags: Vec::new(),
+            kind,
+            range: FragRange::new(span, &self.utf16),
+            children: Vec::new(),
+        });
+    }
+
+    fn push_frag(&mut self, frag: FragSpecs) {
+        let node = self.stack.last_mut().unwrap();
+        node.frags.push(frag);
+
+        self.ranges
+            .entry(frag)
+            .or_default()
+            .push(node.range.clone());
+    }
+
+    fn leave(&mut self) {
+        let node = self.stack.pop().unwrap();
+
+        let siblings = match self.stack.last_mut() {
+            Some(parent) => &mut parent.children,
+            None => &mut self.tree,
+        };
+
+        // a node accepted by no fragment specifier is left out, but not its descendants
+        if node.frags.is_empty() {
+            siblings.extend(node.children);
+        } else {
+            siblings.push(node);
+        }
+    }
 }
 
 macro_rules! impl_colored {
@@ -95,8 +285,12 @@ macro_rules! impl_colored {
             pub fn new(inner: $base_ty) -> Self {
                 Self {
                     inner,
-                    visit: |v, node| ::syn::visit::$fn_name(v, node),
+                    visit: |v, node| Visit::$fn_name(v, node),
                     ranges: HashMap::new(),
+                    tree: Vec::new(),
+                    edition: Edition::default(),
+                    utf16: Utf16Index::default(),
+                    stack: Vec::new(),
                 }
             }
         }
@@ -116,3 +310,67 @@ impl_colored!(ColoredPath, syn::Path, visit_path);
 impl_colored!(ColoredStmt, syn::Stmt, visit_stmt);
 impl_colored!(ColoredType, syn::Type, visit_type);
 impl_colored!(ColoredVisibility, syn::Visibility, visit_visibility);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots(matcher: WholeMatcher, content: &str) -> Vec<(&'static str, Vec<FragSpecs>)> {
+        matcher
+            .try_parse(content, Edition::E2021)
+            .unwrap()
+            .tree
+            .into_iter()
+            .map(|node| (node.kind, node.frags))
+            .collect()
+    }
+
+    #[test]
+    fn tree_starts_at_the_matched_node() {
+        assert_eq!(
+            roots(WholeMatcher::Stmt, "let x = f(1);"),
+            [("Local", vec![FragSpecs::Stmt])]
+        );
+        assert_eq!(
+            roots(WholeMatcher::Expr, "1 + 2"),
+            [("ExprBinary", vec![FragSpecs::Expr2021, FragSpecs::Expr])]
+        );
+        assert_eq!(
+            roots(WholeMatcher::Type, "Vec<u8>"),
+            [("TypePath", vec![FragSpecs::Ty])]
+        );
+        assert_eq!(
+            roots(WholeMatcher::Ident, "x"),
+            [("Ident", vec![FragSpecs::Ident])]
+        );
+    }
+
+    #[test]
+    fn root_is_in_the_ranges() {
+        let fragments = WholeMatcher::Stmt
+            .try_parse("f(1);", Edition::E2021)
+            .unwrap();
+
+        assert_eq!(fragments.byte_ranges()[&FragSpecs::Stmt], vec![0..5]);
+    }
+
+    #[test]
+    fn file_is_not_a_fragment() {
+        assert_eq!(
+            roots(WholeMatcher::File, "fn f() {} struct S;"),
+            [
+                ("ItemFn", vec![FragSpecs::Item]),
+                ("ItemStruct", vec![FragSpecs::Item])
+            ]
+        );
+    }
+
+    #[test]
+    fn omitted_visibility_is_left_out() {
+        let fragments = WholeMatcher::Item
+            .try_parse("fn f() {}", Edition::E2021)
+            .unwrap();
+
+        assert!(!fragments.ranges.contains_key(&FragSpecs::Vis));
+    }
+}
@@ -0,0 +1,14 @@
+//! Macro-by-example ( `macro_rules!` ) emulation which follows rustc's rules rather than syn's.
+
+mod fragment;
+mod macro_rules;
+mod transcriber;
+
+pub use fragment::{
+    can_follow, match_fragment, match_fragment_str, match_whole_str, Follower, FragmentMatch,
+};
+pub use macro_rules::{
+    ArmError, ArmMatch, Binding, BoundFragment, MacroArm, MacroMatch, MacroRules, MetaVarKind,
+    NamedBinding, RepeatOp,
+};
+pub use transcriber::{ExpandedToken, Expansion, TokenOrigin};
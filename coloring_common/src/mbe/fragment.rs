@@ -0,0 +1,473 @@
+use crate::{Edition, FragSpecs};
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use std::ops::Range;
+use syn::ext::IdentExt;
+use syn::parse::{ParseStream, Parser};
+use syn::Token;
+
+// ref: https://doc.rust-lang.org/reference/macros-by-example.html#metavariables
+
+/// Result of matching `$x:frag` against the head of a token sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentMatch {
+    /// Number of `proc_macro2` token trees consumed.
+    /// Note that a lifetime ( `'a` ) is counted as 2 token trees.
+    pub consumed: usize,
+    pub byte_range: Range<usize>,
+}
+
+pub fn match_fragment_str(
+    frag: FragSpecs,
+    content: &str,
+    edition: Edition,
+) -> syn::Result<FragmentMatch> {
+    let tokens = edition.tokenize(content)?;
+
+    match_fragment(frag, &tokens.into_iter().collect::<Vec<_>>(), edition)
+}
+
+/// Same as [`match_fragment_str`] but requires the whole `content` to be consumed,
+/// which is what `$x:frag` does when it is the only thing in a matcher.
+pub fn match_whole_str(
+    frag: FragSpecs,
+    content: &str,
+    edition: Edition,
+) -> syn::Result<FragmentMatch> {
+    let tokens = edition.tokenize(content)?.into_iter().collect::<Vec<_>>();
+
+    let res = match_fragment(frag, &tokens, edition)?;
+
+    match tokens.get(res.consumed) {
+        Some(rest) => Err(syn::Error::new(
+            rest.span(),
+            format!(
+                "no rules expected this token after `{}` fragment",
+                frag.to_string().to_ascii_lowercase()
+            ),
+        )),
+        None => Ok(res),
+    }
+}
+
+/// Answers whether `$x:frag` accepts the head of `tokens`, and how many token trees it consumes.
+pub fn match_fragment(
+    frag: FragSpecs,
+    tokens: &[TokenTree],
+    edition: Edition,
+) -> syn::Result<FragmentMatch> {
+    let stream = tokens.iter().cloned().collect();
+    let (consumed, ()) = consumed_by(
+        |input: ParseStream| parse_fragment(frag, input, edition),
+        stream,
+        tokens.len(),
+    )?;
+
+    let byte_range = match (tokens.first(), consumed.checked_sub(1)) {
+        (Some(first), Some(last)) => {
+            let Some(last) = tokens.get(last) else {
+                return Err(syn::Error::new(
+                    first.span(),
+                    "the fragment is longer than the input",
+                ));
+            };
+            first.span().byte_range().start..last.span().byte_range().end
+        }
+        (Some(first), None) => {
+            let start = first.span().byte_range().start;
+            start..start
+        }
+        (None, _) => 0..0,
+    };
+
+    Ok(FragmentMatch {
+        consumed,
+        byte_range,
+    })
+}
+
+fn consumed_by<T>(
+    parser: impl FnOnce(ParseStream) -> syn::Result<T>,
+    stream: TokenStream,
+    len: usize,
+) -> syn::Result<(usize, T)> {
+    let parser = |input: ParseStream| {
+        let res = parser(input)?;
+        let rest: TokenStream = input.parse()?;
+
+        Ok((len - rest.into_iter().count(), res))
+    };
+
+    parser.parse2(stream)
+}
+
+fn parse_fragment(frag: FragSpecs, input: ParseStream, edition: Edition) -> syn::Result<()> {
+    match frag {
+        FragSpecs::Block => {
+            input.parse::<syn::Block>()?;
+        }
+        FragSpecs::Expr | FragSpecs::Expr2021 => {
+            // `let` expressions are only allowed in conditions
+            if input.peek(Token![let]) {
+                return Err(input.error("expected expression, found `let` statement"));
+            }
+            // `const {}` and `_` are accepted by `expr` since edition 2024, but never by `expr_2021`
+            if frag == FragSpecs::Expr2021 || !edition.has_expr_2024() {
+                if input.peek(Token![const]) {
+                    return Err(input.error("expected expression, found `const`"));
+                }
+                if input.peek(Token![_]) {
+                    return Err(input.error("expected expression, found `_`"));
+                }
+            }
+            input.parse::<syn::Expr>()?;
+        }
+        FragSpecs::Ident => {
+            if input.peek(Token![_]) {
+                return Err(input.error("expected identifier, found `_`"));
+            }
+            input.call(syn::Ident::parse_any)?;
+        }
+        FragSpecs::Item => {
+            input.parse::<syn::Item>()?;
+        }
+        FragSpecs::Lifetime => {
+            input.parse::<syn::Lifetime>()?;
+        }
+        FragSpecs::Literal => {
+            if input.peek(Token![-]) {
+                input.parse::<Token![-]>()?;
+            }
+            input.parse::<syn::Lit>()?;
+        }
+        FragSpecs::Meta => {
+            input.parse::<syn::Meta>()?;
+        }
+        FragSpecs::Pat if edition.has_or_patterns() => {
+            input.call(syn::Pat::parse_multi_with_leading_vert)?;
+        }
+        FragSpecs::Pat => {
+            input.call(syn::Pat::parse_single)?;
+        }
+        FragSpecs::PatParam => {
+            input.call(syn::Pat::parse_single)?;
+        }
+        FragSpecs::Path => {
+            input.parse::<syn::Path>()?;
+        }
+        FragSpecs::Stmt => {
+            parse_stmt(input)?;
+        }
+        FragSpecs::Ty => {
+            input.parse::<syn::Type>()?;
+        }
+        FragSpecs::Vis => {
+            // may be empty
+            input.parse::<syn::Visibility>()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a statement without its trailing semicolon, as rustc's `parse_stmt_without_recovery`
+/// does: a `let` without `;` , an item, or an expression which needs no `;` after it.
+fn parse_stmt(input: ParseStream) -> syn::Result<()> {
+    input.call(syn::Attribute::parse_outer)?;
+
+    if input.peek(Token![let]) {
+        input.parse::<Token![let]>()?;
+        input.call(syn::Pat::parse_multi_with_leading_vert)?;
+        if input.peek(Token![:]) {
+            input.parse::<Token![:]>()?;
+            input.parse::<syn::Type>()?;
+        }
+        if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            input.parse::<syn::Expr>()?;
+            if input.peek(Token![else]) {
+                input.parse::<Token![else]>()?;
+                input.parse::<syn::Block>()?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // an item keeps its own `;` , e.g. `struct S;`
+    let item_error = match input.fork().parse::<syn::Item>() {
+        Ok(_) => {
+            input.parse::<syn::Item>()?;
+
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+
+    // `expected an expression` tells nothing for `struct S`
+    if starts_like_item(input) {
+        return Err(item_error);
+    }
+
+    input.parse::<syn::Expr>()?;
+
+    Ok(())
+}
+
+/// Whether the head is a keyword which only an item starts with.
+fn starts_like_item(input: ParseStream) -> bool {
+    input.peek(Token![pub])
+        || input.peek(Token![struct])
+        || input.peek(Token![enum])
+        || input.peek(Token![fn])
+        || input.peek(Token![use])
+        || input.peek(Token![mod])
+        || input.peek(Token![impl])
+        || input.peek(Token![trait])
+        || input.peek(Token![type])
+        || input.peek(Token![static])
+        || input.peek(Token![extern])
+}
+
+/// What comes next to a fragment in a `macro_rules!` matcher.
+#[derive(Debug, Clone, Copy)]
+pub enum Follower<'a> {
+    /// Literal tokens of the matcher. Empty means the end of the matcher (or its group).
+    Tokens(&'a [TokenTree]),
+    /// Another metavariable.
+    Fragment(FragSpecs),
+    /// A `$t:tt` metavariable, which may be any token.
+    AnyToken,
+}
+
+/// Checks rustc's follow-set restrictions, which keep `macro_rules!` matchers future-proof.
+pub fn can_follow(frag: FragSpecs, follower: Follower<'_>, edition: Edition) -> bool {
+    let follower = match follower {
+        Follower::Tokens(tokens) => match tokens.first() {
+            None => return true,
+            Some(TokenTree::Group(group)) => Next::Open(group.delimiter()),
+            Some(TokenTree::Ident(ident)) => Next::Ident(ident.to_string()),
+            Some(TokenTree::Punct(_)) => Next::Punct(leading_op(tokens)),
+            Some(TokenTree::Literal(_)) => Next::Literal,
+        },
+        Follower::Fragment(frag) => Next::Fragment(frag),
+        Follower::AnyToken => Next::Any,
+    };
+
+    match frag {
+        FragSpecs::Expr | FragSpecs::Expr2021 | FragSpecs::Stmt => {
+            matches!(&follower, Next::Punct(op) if ["=>", ",", ";"].contains(&op.as_str()))
+        }
+        FragSpecs::Pat if edition.has_or_patterns() => match &follower {
+            Next::Punct(op) => ["=>", ",", "="].contains(&op.as_str()),
+            Next::Ident(ident) => ident == "if" || ident == "in",
+            _ => false,
+        },
+        FragSpecs::Pat | FragSpecs::PatParam => match &follower {
+            Next::Punct(op) => ["=>", ",", "=", "|"].contains(&op.as_str()),
+            Next::Ident(ident) => ident == "if" || ident == "in",
+            _ => false,
+        },
+        FragSpecs::Path | FragSpecs::Ty => match &follower {
+            Next::Punct(op) => ["=>", ",", "=", "|", ";", ":", ">", ">>"].contains(&op.as_str()),
+            Next::Ident(ident) => ident == "as" || ident == "where",
+            Next::Open(delim) => matches!(delim, Delimiter::Bracket | Delimiter::Brace),
+            Next::Fragment(frag) => *frag == FragSpecs::Block,
+            Next::Literal | Next::Any => false,
+        },
+        FragSpecs::Vis => match &follower {
+            // `,` or any token which can begin a type
+            Next::Punct(op) => {
+                [",", "!", "*", "&", "&&", "?", "'", "<", "<<", "::"].contains(&op.as_str())
+            }
+            Next::Ident(ident) => ident != "priv",
+            Next::Open(delim) => matches!(delim, Delimiter::Parenthesis | Delimiter::Bracket),
+            Next::Fragment(frag) => {
+                matches!(frag, FragSpecs::Ident | FragSpecs::Ty | FragSpecs::Path)
+            }
+            Next::Literal | Next::Any => false,
+        },
+        FragSpecs::Block
+        | FragSpecs::Ident
+        | FragSpecs::Item
+        | FragSpecs::Lifetime
+        | FragSpecs::Literal
+        | FragSpecs::Meta => true,
+    }
+}
+
+enum Next {
+    Open(Delimiter),
+    Ident(String),
+    Punct(String),
+    Literal,
+    Fragment(FragSpecs),
+    Any,
+}
+
+// multi character operators in rustc's lexer
+const OPS: [&str; 24] = [
+    "<<=", ">>=", "...", "..=", "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "^=", "&=", "|=", "<<", ">>", "..",
+];
+
+/// Glues the leading joint puncts into one rustc operator token.
+pub(super) fn leading_op(tokens: &[TokenTree]) -> String {
+    let mut joint = String::new();
+    for token in tokens {
+        let TokenTree::Punct(punct) = token else {
+            break;
+        };
+
+        joint.push(punct.as_char());
+
+        if punct.spacing() == Spacing::Alone {
+            break;
+        }
+    }
+
+    OPS.iter()
+        .find(|op| joint.starts_with(*op))
+        .map(|op| op.to_string())
+        .unwrap_or_else(|| joint.chars().take(1).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consumed(frag: FragSpecs, content: &str) -> syn::Result<usize> {
+        match_fragment_str(frag, content, Edition::E2021).map(|m| m.consumed)
+    }
+
+    #[test]
+    fn stmt_followed_by_comma() {
+        assert_eq!(consumed(FragSpecs::Stmt, "let a = 1, b").unwrap(), 4);
+        assert_eq!(consumed(FragSpecs::Stmt, "b, c").unwrap(), 1);
+    }
+
+    #[test]
+    fn stmt_followed_by_semicolon() {
+        assert_eq!(consumed(FragSpecs::Stmt, "x + 1; y").unwrap(), 3);
+        assert_eq!(consumed(FragSpecs::Stmt, "let a: u8; y").unwrap(), 4);
+    }
+
+    #[test]
+    fn stmt_followed_by_fat_arrow() {
+        assert_eq!(consumed(FragSpecs::Stmt, "f() => 1").unwrap(), 2);
+        assert_eq!(
+            consumed(FragSpecs::Stmt, "if a {} else {} => 1").unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn stmt_item_keeps_its_semicolon() {
+        assert_eq!(consumed(FragSpecs::Stmt, "struct S;").unwrap(), 3);
+        assert_eq!(consumed(FragSpecs::Stmt, "fn f() {} , x").unwrap(), 4);
+    }
+
+    #[test]
+    fn stmt_item_without_semicolon_is_an_error() {
+        assert!(consumed(FragSpecs::Stmt, "struct S").is_err());
+        assert!(consumed(FragSpecs::Stmt, "use a::b").is_err());
+    }
+
+    #[test]
+    fn expr_2024_accepts_const_blocks_and_underscores() {
+        let edition = Edition::E2024;
+
+        assert!(match_fragment_str(FragSpecs::Expr, "const { 1 }", edition).is_ok());
+        assert!(match_fragment_str(FragSpecs::Expr, "_", edition).is_ok());
+        assert!(match_fragment_str(FragSpecs::Expr2021, "const { 1 }", edition).is_err());
+        assert!(match_fragment_str(FragSpecs::Expr2021, "_", edition).is_err());
+    }
+
+    #[test]
+    fn expr_before_2024_rejects_const_blocks_and_underscores() {
+        let edition = Edition::E2021;
+
+        assert!(match_fragment_str(FragSpecs::Expr, "const { 1 }", edition).is_err());
+        assert!(match_fragment_str(FragSpecs::Expr, "_", edition).is_err());
+    }
+
+    #[test]
+    fn expr_rejects_let() {
+        assert!(consumed(FragSpecs::Expr, "let x = 1").is_err());
+    }
+
+    #[test]
+    fn whole_str_rejects_the_rest() {
+        assert!(match_whole_str(FragSpecs::Expr, "1 + 2", Edition::E2021).is_ok());
+        assert!(match_whole_str(FragSpecs::Ty, "u8 u8", Edition::E2021).is_err());
+    }
+
+    fn can_follow_str(frag: FragSpecs, follower: &str, edition: Edition) -> bool {
+        let tokens = follower
+            .parse::<TokenStream>()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        can_follow(frag, Follower::Tokens(&tokens), edition)
+    }
+
+    #[test]
+    fn follow_set_of_expr_and_stmt() {
+        for frag in [FragSpecs::Expr, FragSpecs::Expr2021, FragSpecs::Stmt] {
+            for ok in ["=>", ",", ";"] {
+                assert!(can_follow_str(frag, ok, Edition::E2021), "{} {}", frag, ok);
+            }
+            for ng in ["+", "=", "(a)", "a", "1"] {
+                assert!(!can_follow_str(frag, ng, Edition::E2021), "{} {}", frag, ng);
+            }
+        }
+    }
+
+    #[test]
+    fn follow_set_of_pat_depends_on_edition() {
+        assert!(can_follow_str(FragSpecs::Pat, "|", Edition::E2018));
+        assert!(!can_follow_str(FragSpecs::Pat, "|", Edition::E2021));
+        assert!(can_follow_str(FragSpecs::PatParam, "|", Edition::E2021));
+        assert!(can_follow_str(FragSpecs::Pat, "if", Edition::E2021));
+    }
+
+    #[test]
+    fn follow_set_of_ty_and_path() {
+        for frag in [FragSpecs::Ty, FragSpecs::Path] {
+            for ok in ["=>", ">>", "as", "where", "{}", "[]"] {
+                assert!(can_follow_str(frag, ok, Edition::E2021), "{} {}", frag, ok);
+            }
+            for ng in ["+", "()", "a", "-"] {
+                assert!(!can_follow_str(frag, ng, Edition::E2021), "{} {}", frag, ng);
+            }
+            assert!(can_follow(
+                frag,
+                Follower::Fragment(FragSpecs::Block),
+                Edition::E2021
+            ));
+            assert!(!can_follow(frag, Follower::AnyToken, Edition::E2021));
+        }
+    }
+
+    #[test]
+    fn follow_set_of_vis() {
+        assert!(can_follow_str(FragSpecs::Vis, "fn", Edition::E2021));
+        assert!(!can_follow_str(FragSpecs::Vis, "priv", Edition::E2021));
+        assert!(!can_follow_str(FragSpecs::Vis, "1", Edition::E2021));
+    }
+
+    #[test]
+    fn anything_follows_the_others() {
+        for frag in [FragSpecs::Ident, FragSpecs::Block, FragSpecs::Item] {
+            assert!(can_follow(frag, Follower::AnyToken, Edition::E2021));
+        }
+    }
+
+    #[test]
+    fn stmt_let_else() {
+        assert_eq!(
+            consumed(FragSpecs::Stmt, "let Some(a) = b else { return }, c").unwrap(),
+            7
+        );
+    }
+}
@@ -0,0 +1,1315 @@
+use super::fragment::{can_follow, leading_op, match_fragment, Follower};
+use super::transcriber::{parse_transcriber, transcribe, Expansion, TranscriberTree};
+use crate::{Edition, FragSpecs, RangeMap};
+use clap::ValueEnum;
+use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use syn::parse::{ParseStream, Parser};
+use syn::Token;
+
+// ref: https://github.com/rust-lang/rust/blob/master/compiler/rustc_expand/src/mbe/macro_parser.rs
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetaVarKind {
+    Frag(FragSpecs),
+    Tt,
+}
+
+impl MetaVarKind {
+    fn parse(ident: &Ident) -> syn::Result<Self> {
+        let name = ident.to_string();
+
+        if name == "tt" {
+            return Ok(MetaVarKind::Tt);
+        }
+
+        FragSpecs::from_str(&name, false)
+            .map(MetaVarKind::Frag)
+            .map_err(|_| {
+                syn::Error::new(
+                    ident.span(),
+                    format!("invalid fragment specifier `{}`", name),
+                )
+            })
+    }
+}
+
+impl fmt::Display for MetaVarKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetaVarKind::Frag(frag) => write!(f, "{}", frag.to_string().to_ascii_lowercase()),
+            MetaVarKind::Tt => write!(f, "tt"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatOp {
+    /// `*`
+    ZeroOrMore,
+    /// `+`
+    OneOrMore,
+    /// `?`
+    ZeroOrOne,
+}
+
+impl RepeatOp {
+    fn from_op(op: &str) -> Option<Self> {
+        match op {
+            "*" => Some(RepeatOp::ZeroOrMore),
+            "+" => Some(RepeatOp::OneOrMore),
+            "?" => Some(RepeatOp::ZeroOrOne),
+            _ => None,
+        }
+    }
+}
+
+/// A token as rustc's lexer sees it: joint puncts are glued into one operator and `'a` is one lifetime.
+#[derive(Debug, Clone)]
+pub(super) struct Tok {
+    kind: TokKind,
+    text: String,
+    trees: Vec<TokenTree>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokKind {
+    Ident,
+    Punct,
+    Literal,
+    Lifetime,
+}
+
+impl PartialEq for Tok {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.text == other.text
+    }
+}
+
+impl Tok {
+    /// Glues the leaf token trees at the head of `trees`. Returns `None` for a group.
+    pub(super) fn glue(trees: &[TokenTree]) -> Option<Self> {
+        let (kind, text, width) = match trees.first()? {
+            TokenTree::Group(_) => return None,
+            TokenTree::Ident(ident) => (TokKind::Ident, ident.to_string(), 1),
+            TokenTree::Literal(lit) => (TokKind::Literal, lit.to_string(), 1),
+            TokenTree::Punct(punct) => match (punct.as_char(), trees.get(1)) {
+                ('\'', Some(TokenTree::Ident(ident))) => {
+                    (TokKind::Lifetime, format!("'{}", ident), 2)
+                }
+                _ => {
+                    let op = leading_op(trees);
+                    let width = op.chars().count();
+                    (TokKind::Punct, op, width)
+                }
+            },
+        };
+
+        Some(Tok {
+            kind,
+            text,
+            trees: trees[..width].to_vec(),
+        })
+    }
+
+    pub(super) fn span(&self) -> Span {
+        self.trees[0].span()
+    }
+
+    pub(super) fn trees(&self) -> &[TokenTree] {
+        &self.trees
+    }
+
+    pub(super) fn byte_range(&self) -> Range<usize> {
+        self.span().byte_range().start..self.trees.last().unwrap().span().byte_range().end
+    }
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MatcherTree {
+    Token(Tok),
+    Group {
+        tree: TokenTree,
+        delimiter: Delimiter,
+        trees: Vec<MatcherTree>,
+    },
+    MetaVar {
+        name: Ident,
+        kind: MetaVarKind,
+    },
+    Repeat {
+        trees: Vec<MatcherTree>,
+        separator: Option<Tok>,
+        op: RepeatOp,
+        span: Span,
+    },
+}
+
+fn parse_matcher(trees: &[TokenTree]) -> syn::Result<Vec<MatcherTree>> {
+    let mut res = Vec::new();
+    let mut i = 0;
+
+    while i < trees.len() {
+        match &trees[i] {
+            TokenTree::Punct(dollar) if dollar.as_char() == '$' => match trees.get(i + 1) {
+                Some(TokenTree::Ident(name)) => {
+                    let (Some(TokenTree::Punct(colon)), Some(TokenTree::Ident(kind))) =
+                        (trees.get(i + 2), trees.get(i + 3))
+                    else {
+                        return Err(syn::Error::new(name.span(), "missing fragment specifier"));
+                    };
+
+                    if colon.as_char() != ':' {
+                        return Err(syn::Error::new(colon.span(), "expected `:`"));
+                    }
+
+                    res.push(MatcherTree::MetaVar {
+                        name: name.clone(),
+                        kind: MetaVarKind::parse(kind)?,
+                    });
+                    i += 4;
+                }
+                Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                    let inner = parse_matcher(&group.stream().into_iter().collect::<Vec<_>>())?;
+                    let (separator, op, width) = parse_repeat_op(&trees[i + 2..], group.span())?;
+
+                    res.push(MatcherTree::Repeat {
+                        trees: inner,
+                        separator,
+                        op,
+                        span: group.span(),
+                    });
+                    i += 2 + width;
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        dollar.span(),
+                        "expected identifier or `(` after `$`",
+                    ))
+                }
+            },
+            TokenTree::Group(group) => {
+                let inner = parse_matcher(&group.stream().into_iter().collect::<Vec<_>>())?;
+
+                res.push(MatcherTree::Group {
+                    tree: trees[i].clone(),
+                    delimiter: group.delimiter(),
+                    trees: inner,
+                });
+                i += 1;
+            }
+            _ => {
+                let tok = Tok::glue(&trees[i..]).unwrap();
+
+                i += tok.trees.len();
+                res.push(MatcherTree::Token(tok));
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Parses `sep? op` following `$(...)`. Returns the separator, the operator and the number of token trees read.
+pub(super) fn parse_repeat_op(
+    trees: &[TokenTree],
+    span: Span,
+) -> syn::Result<(Option<Tok>, RepeatOp, usize)> {
+    let Some(first) = Tok::glue(trees) else {
+        return Err(syn::Error::new(span, "expected one of: `*`, `+`, or `?`"));
+    };
+
+    let second = Tok::glue(&trees[first.trees.len()..]);
+    let second_op = second
+        .as_ref()
+        .and_then(|t| RepeatOp::from_op(&t.text))
+        .filter(|op| *op != RepeatOp::ZeroOrOne);
+
+    match (RepeatOp::from_op(&first.text), second_op) {
+        (Some(op), _) => Ok((None, op, first.trees.len())),
+        (None, Some(op)) => {
+            let width = first.trees.len() + 1;
+            Ok((Some(first), op, width))
+        }
+        (None, None) => Err(syn::Error::new(
+            first.span(),
+            "expected one of: `*` or `+` after the separator",
+        )),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Loc {
+    Token(Tok),
+    Open(Delimiter),
+    Close(Delimiter),
+    Sequence {
+        op: RepeatOp,
+        num_metavar_decls: usize,
+        idx_first_after: usize,
+        next_metavar: usize,
+        seq_depth: usize,
+    },
+    SequenceKleeneOpNoSep {
+        op: RepeatOp,
+        idx_first: usize,
+    },
+    SequenceSep {
+        separator: Tok,
+    },
+    SequenceKleeneOpAfterSep {
+        idx_first: usize,
+    },
+    MetaVarDecl {
+        kind: MetaVarKind,
+        next_metavar: usize,
+        seq_depth: usize,
+    },
+    Eof,
+}
+
+fn compute_locs(trees: &[MatcherTree]) -> Vec<Loc> {
+    fn inner(
+        trees: &[MatcherTree],
+        locs: &mut Vec<Loc>,
+        next_metavar: &mut usize,
+        seq_depth: usize,
+    ) {
+        for tree in trees {
+            match tree {
+                MatcherTree::Token(tok) => locs.push(Loc::Token(tok.clone())),
+                MatcherTree::Group {
+                    delimiter, trees, ..
+                } => {
+                    locs.push(Loc::Open(*delimiter));
+                    inner(trees, locs, next_metavar, seq_depth);
+                    locs.push(Loc::Close(*delimiter));
+                }
+                MatcherTree::Repeat {
+                    trees,
+                    separator,
+                    op,
+                    ..
+                } => {
+                    let idx_sequence = locs.len();
+                    let idx_first = idx_sequence + 1;
+                    let next_metavar_before = *next_metavar;
+
+                    // placeholder
+                    locs.push(Loc::Eof);
+
+                    inner(trees, locs, next_metavar, seq_depth + 1);
+
+                    match separator {
+                        Some(separator) => {
+                            locs.push(Loc::SequenceSep {
+                                separator: separator.clone(),
+                            });
+                            locs.push(Loc::SequenceKleeneOpAfterSep { idx_first });
+                        }
+                        None => locs.push(Loc::SequenceKleeneOpNoSep { op: *op, idx_first }),
+                    }
+
+                    locs[idx_sequence] = Loc::Sequence {
+                        op: *op,
+                        num_metavar_decls: *next_metavar - next_metavar_before,
+                        idx_first_after: locs.len(),
+                        next_metavar: next_metavar_before,
+                        seq_depth,
+                    };
+                }
+                MatcherTree::MetaVar { kind, .. } => {
+                    locs.push(Loc::MetaVarDecl {
+                        kind: *kind,
+                        next_metavar: *next_metavar,
+                        seq_depth,
+                    });
+                    *next_metavar += 1;
+                }
+            }
+        }
+    }
+
+    let mut locs = Vec::new();
+    inner(trees, &mut locs, &mut 0, 0);
+    locs.push(Loc::Eof);
+
+    locs
+}
+
+fn metavar_decls(trees: &[MatcherTree], res: &mut Vec<(Ident, MetaVarKind)>) {
+    for tree in trees {
+        match tree {
+            MatcherTree::Token(_) => {}
+            MatcherTree::Group { trees, .. } | MatcherTree::Repeat { trees, .. } => {
+                metavar_decls(trees, res)
+            }
+            MatcherTree::MetaVar { name, kind } => res.push((name.clone(), *kind)),
+        }
+    }
+}
+
+fn can_match_empty(trees: &[MatcherTree]) -> bool {
+    trees.iter().all(|tree| match tree {
+        MatcherTree::Token(_) | MatcherTree::Group { .. } => false,
+        MatcherTree::MetaVar { kind, .. } => *kind == MetaVarKind::Frag(FragSpecs::Vis),
+        MatcherTree::Repeat { trees, op, .. } => {
+            *op != RepeatOp::OneOrMore || can_match_empty(trees)
+        }
+    })
+}
+
+/// Possible first tokens of a matcher sequence, as followers of the preceding metavariable.
+fn firsts(trees: &[MatcherTree]) -> Vec<Follower<'_>> {
+    let mut res = Vec::new();
+
+    for tree in trees {
+        match tree {
+            MatcherTree::Token(tok) => {
+                res.push(Follower::Tokens(&tok.trees));
+                return res;
+            }
+            MatcherTree::Group { tree, .. } => {
+                res.push(Follower::Tokens(std::slice::from_ref(tree)));
+                return res;
+            }
+            MatcherTree::MetaVar { kind, .. } => {
+                res.push(match kind {
+                    MetaVarKind::Frag(frag) => Follower::Fragment(*frag),
+                    MetaVarKind::Tt => Follower::AnyToken,
+                });
+                if *kind != MetaVarKind::Frag(FragSpecs::Vis) {
+                    return res;
+                }
+            }
+            MatcherTree::Repeat { trees, op, .. } => {
+                res.extend(firsts(trees).into_iter().filter(|f| !is_end(f)));
+                if *op == RepeatOp::OneOrMore && !can_match_empty(trees) {
+                    return res;
+                }
+            }
+        }
+    }
+
+    // the end of the matcher
+    res.push(Follower::Tokens(&[]));
+
+    res
+}
+
+fn is_end(follower: &Follower<'_>) -> bool {
+    matches!(follower, Follower::Tokens(tokens) if tokens.is_empty())
+}
+
+fn follower_to_string(follower: &Follower<'_>) -> String {
+    match follower {
+        Follower::Tokens(tokens) => match Tok::glue(tokens) {
+            Some(tok) => tok.text,
+            None => match tokens.first() {
+                Some(TokenTree::Group(group)) => match group.delimiter() {
+                    Delimiter::Parenthesis => "(".to_string(),
+                    Delimiter::Brace => "{".to_string(),
+                    Delimiter::Bracket => "[".to_string(),
+                    Delimiter::None => "".to_string(),
+                },
+                _ => "".to_string(),
+            },
+        },
+        Follower::Fragment(frag) => format!("$_:{}", MetaVarKind::Frag(*frag)),
+        Follower::AnyToken => "$_:tt".to_string(),
+    }
+}
+
+/// Checks follow-set restrictions of every metavariable.
+/// `outer` is what may come after the end of `trees`.
+fn check_follow(
+    trees: &[MatcherTree],
+    outer: &[Follower<'_>],
+    edition: Edition,
+) -> syn::Result<()> {
+    for (i, tree) in trees.iter().enumerate() {
+        let followers = || {
+            let mut followers = firsts(&trees[i + 1..]);
+            if followers.last().is_some_and(is_end) {
+                followers.pop();
+                followers.extend_from_slice(outer);
+            }
+            followers
+        };
+
+        match tree {
+            MatcherTree::Token(_)
+            | MatcherTree::MetaVar {
+                kind: MetaVarKind::Tt,
+                ..
+            } => {}
+            MatcherTree::MetaVar {
+                name,
+                kind: MetaVarKind::Frag(frag),
+            } => {
+                for follower in followers() {
+                    if !can_follow(*frag, follower, edition) {
+                        let kind = MetaVarKind::Frag(*frag);
+
+                        return Err(syn::Error::new(
+                            name.span(),
+                            format!(
+                                "`${}:{}` is followed by `{}`, which is not allowed for `{}` fragments",
+                                name,
+                                kind,
+                                follower_to_string(&follower),
+                                kind
+                            ),
+                        ));
+                    }
+                }
+            }
+            MatcherTree::Group { trees, .. } => {
+                // the closing delimiter may follow anything
+                check_follow(trees, &[Follower::Tokens(&[])], edition)?;
+            }
+            MatcherTree::Repeat {
+                trees: inner,
+                separator,
+                ..
+            } => {
+                // the end of a repetition is followed by its separator or its beginning,
+                // or by what comes after the repetition.
+                let mut inner_outer = match separator {
+                    Some(separator) => vec![Follower::Tokens(&separator.trees)],
+                    None => firsts(inner).into_iter().filter(|f| !is_end(f)).collect(),
+                };
+                inner_outer.extend(followers());
+
+                check_follow(inner, &inner_outer, edition)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_repeats(trees: &[MatcherTree]) -> syn::Result<()> {
+    for tree in trees {
+        match tree {
+            MatcherTree::Token(_) | MatcherTree::MetaVar { .. } => {}
+            MatcherTree::Group { trees, .. } => check_repeats(trees)?,
+            MatcherTree::Repeat { trees, span, .. } => {
+                if can_match_empty(trees) {
+                    return Err(syn::Error::new(
+                        *span,
+                        "repetition matches empty token tree",
+                    ));
+                }
+                check_repeats(trees)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct MacroArm {
+    pub matcher: Group,
+    pub transcriber: Group,
+    pub metavars: Vec<(Ident, MetaVarKind)>,
+    locs: Vec<Loc>,
+    transcriber_trees: Vec<TranscriberTree>,
+}
+
+impl MacroArm {
+    fn new(matcher: Group, transcriber: Group, edition: Edition) -> syn::Result<Self> {
+        let trees = parse_matcher(&matcher.stream().into_iter().collect::<Vec<_>>())?;
+
+        let mut metavars = Vec::new();
+        metavar_decls(&trees, &mut metavars);
+
+        for (i, (name, _)) in metavars.iter().enumerate() {
+            if metavars[..i].iter().any(|(n, _)| n == name) {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("duplicate matcher binding `${}`", name),
+                ));
+            }
+        }
+
+        check_repeats(&trees)?;
+        check_follow(&trees, &[Follower::Tokens(&[])], edition)?;
+
+        let transcriber_trees =
+            parse_transcriber(&transcriber.stream().into_iter().collect::<Vec<_>>())?;
+
+        Ok(Self {
+            matcher,
+            transcriber,
+            metavars,
+            locs: compute_locs(&trees),
+            transcriber_trees,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MacroRules {
+    pub name: Ident,
+    pub arms: Vec<MacroArm>,
+    pub edition: Edition,
+}
+
+impl MacroRules {
+    /// Parses `macro_rules! name { (matcher) => { transcriber }; ... }` .
+    pub fn parse(definition: &str, edition: Edition) -> syn::Result<Self> {
+        let tokens = edition.tokenize(definition)?;
+        let item = syn::parse2::<syn::ItemMacro>(tokens)?;
+
+        if !item.mac.path.is_ident("macro_rules") {
+            return Err(syn::Error::new_spanned(
+                &item.mac.path,
+                "expected `macro_rules!`",
+            ));
+        }
+
+        let Some(name) = item.ident else {
+            return Err(syn::Error::new_spanned(
+                &item.mac,
+                "expected a name of the macro",
+            ));
+        };
+
+        let parser = |input: ParseStream| {
+            let mut arms = Vec::new();
+
+            while !input.is_empty() {
+                let matcher: Group = input.parse()?;
+                input.parse::<Token![=>]>()?;
+                let transcriber: Group = input.parse()?;
+
+                arms.push(MacroArm::new(matcher, transcriber, edition)?);
+
+                if input.is_empty() {
+                    break;
+                }
+                input.parse::<Token![;]>()?;
+            }
+
+            Ok(arms)
+        };
+
+        let arms = parser.parse2(item.mac.tokens)?;
+
+        Ok(Self {
+            name,
+            arms,
+            edition,
+        })
+    }
+
+    /// Matches `invocation` against each arm in order, as rustc does.
+    /// `invocation` may be either a whole macro call ( `name!(...)` or `path::name!(...)` )
+    /// or only its input tokens. A call of another macro is an error.
+    pub fn match_invocation(&self, invocation: &str) -> syn::Result<MacroMatch> {
+        let tokens = self.edition.tokenize(invocation)?;
+        let tokens = match syn::parse2::<syn::Macro>(tokens.clone()) {
+            Ok(mac)
+                if mac
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|s| s.ident == self.name) =>
+            {
+                mac.tokens
+            }
+            Ok(mac) => {
+                let path = mac
+                    .path
+                    .segments
+                    .iter()
+                    .map(|s| s.ident.to_string())
+                    .collect::<Vec<_>>()
+                    .join("::");
+                return Err(syn::Error::new_spanned(
+                    mac.path,
+                    format!("`{}!` is not an invocation of `{}!`", path, self.name),
+                ));
+            }
+            Err(_) => tokens,
+        };
+
+        let input = FlatInput::new(tokens);
+
+        let mut errors = Vec::new();
+        for (i, arm) in self.arms.iter().enumerate() {
+            match TtParser::new(&arm.locs, &input, self.edition).parse() {
+                Ok(matches) => {
+                    let bindings = arm
+                        .metavars
+                        .iter()
+                        .zip(matches)
+                        .map(|((name, kind), binding)| NamedBinding {
+                            name: name.to_string(),
+                            kind: *kind,
+                            binding,
+                        })
+                        .collect();
+
+                    return Ok(MacroMatch {
+                        matched: Some(ArmMatch { arm: i, bindings }),
+                        errors,
+                    });
+                }
+                Err(error) => errors.push(ArmError { arm: i, error }),
+            }
+        }
+
+        Ok(MacroMatch {
+            matched: None,
+            errors,
+        })
+    }
+
+    /// Runs the transcriber of the matched arm with its bindings.
+    pub fn transcribe(&self, arm_match: &ArmMatch) -> syn::Result<Expansion> {
+        transcribe(
+            &self.arms[arm_match.arm].transcriber_trees,
+            &arm_match.bindings,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundFragment {
+    pub tokens: TokenStream,
+    /// Byte range in the invocation.
+    pub byte_range: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Binding {
+    Single(BoundFragment),
+    /// One element per repetition.
+    Seq(Vec<Binding>),
+}
+
+impl Binding {
+    fn push_ranges(&self, kind: MetaVarKind, ranges: &mut RangeMap) {
+        match (self, kind) {
+            (Binding::Single(fragment), MetaVarKind::Frag(frag)) => {
+                ranges
+                    .entry(frag)
+                    .or_default()
+                    .push(fragment.byte_range.clone());
+            }
+            (Binding::Single(_), MetaVarKind::Tt) => {}
+            (Binding::Seq(seq), _) => seq.iter().for_each(|b| b.push_ranges(kind, ranges)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NamedBinding {
+    pub name: String,
+    pub kind: MetaVarKind,
+    pub binding: Binding,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArmMatch {
+    /// Index of the matched arm.
+    pub arm: usize,
+    /// In the order of declarations in the matcher.
+    pub bindings: Vec<NamedBinding>,
+}
+
+impl ArmMatch {
+    /// Byte ranges of the bound fragments, for highlighting the invocation.
+    /// `tt` bindings are not included.
+    pub fn ranges(&self) -> RangeMap {
+        let mut ranges = RangeMap::new();
+
+        for NamedBinding { kind, binding, .. } in &self.bindings {
+            binding.push_ranges(*kind, &mut ranges);
+        }
+
+        ranges
+    }
+}
+
+#[derive(Debug)]
+pub struct ArmError {
+    pub arm: usize,
+    pub error: syn::Error,
+}
+
+#[derive(Debug)]
+pub struct MacroMatch {
+    pub matched: Option<ArmMatch>,
+    /// Errors of the arms tried before the matched one (or all of them).
+    pub errors: Vec<ArmError>,
+}
+
+#[derive(Debug, Clone)]
+enum FlatKind {
+    Tok(Tok),
+    Open(Delimiter),
+    Close(Delimiter),
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+struct FlatTok {
+    kind: FlatKind,
+    span: Span,
+    level: usize,
+    index: usize,
+}
+
+/// Invocation tokens with groups flattened into open and close delimiters, as rustc's macro parser reads them.
+struct FlatInput {
+    toks: Vec<FlatTok>,
+    /// Token trees of each nesting level, for parsing fragments.
+    levels: Vec<Vec<TokenTree>>,
+    /// Index in `toks` of each token tree in `levels`. `None` for the inside of a glued token.
+    positions: Vec<Vec<Option<usize>>>,
+}
+
+impl FlatInput {
+    fn new(tokens: TokenStream) -> Self {
+        let mut input = Self {
+            toks: Vec::new(),
+            levels: Vec::new(),
+            positions: Vec::new(),
+        };
+
+        let trees = tokens.into_iter().collect::<Vec<_>>();
+        let eof_span = trees
+            .last()
+            .map(|t| t.span())
+            .unwrap_or_else(Span::call_site);
+        input.flatten(trees, FlatKind::Eof, eof_span);
+
+        input
+    }
+
+    fn flatten(&mut self, trees: Vec<TokenTree>, end: FlatKind, end_span: Span) {
+        let level = self.levels.len();
+        self.levels.push(trees.clone());
+        self.positions.push(vec![None; trees.len() + 1]);
+
+        let mut index = 0;
+        while index < trees.len() {
+            self.positions[level][index] = Some(self.toks.len());
+
+            match &trees[index] {
+                TokenTree::Group(group) => {
+                    self.toks.push(FlatTok {
+                        kind: FlatKind::Open(group.delimiter()),
+                        span: group.span_open(),
+                        level,
+                        index,
+                    });
+                    self.flatten(
+                        group.stream().into_iter().collect(),
+                        FlatKind::Close(group.delimiter()),
+                        group.span_close(),
+                    );
+                    index += 1;
+                }
+                _ => {
+                    let tok = Tok::glue(&trees[index..]).unwrap();
+                    let width = tok.trees.len();
+
+                    self.toks.push(FlatTok {
+                        span: tok.span(),
+                        kind: FlatKind::Tok(tok),
+                        level,
+                        index,
+                    });
+                    index += width;
+                }
+            }
+        }
+
+        self.positions[level][trees.len()] = Some(self.toks.len());
+        self.toks.push(FlatTok {
+            kind: end,
+            span: end_span,
+            level,
+            index: trees.len(),
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MatcherPos {
+    idx: usize,
+    matches: Rc<Vec<Binding>>,
+}
+
+impl MatcherPos {
+    fn push_match(&mut self, metavar_idx: usize, seq_depth: usize, m: Binding) {
+        let matches = Rc::make_mut(&mut self.matches);
+
+        match seq_depth {
+            0 => {
+                debug_assert_eq!(metavar_idx, matches.len());
+                matches.push(m);
+            }
+            _ => {
+                let mut curr = &mut matches[metavar_idx];
+                for _ in 0..seq_depth - 1 {
+                    match curr {
+                        Binding::Seq(seq) => curr = seq.last_mut().unwrap(),
+                        _ => unreachable!(),
+                    }
+                }
+                match curr {
+                    Binding::Seq(seq) => seq.push(m),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+struct TtParser<'a> {
+    locs: &'a [Loc],
+    input: &'a FlatInput,
+    edition: Edition,
+}
+
+impl<'a> TtParser<'a> {
+    fn new(locs: &'a [Loc], input: &'a FlatInput, edition: Edition) -> Self {
+        Self {
+            locs,
+            input,
+            edition,
+        }
+    }
+
+    fn parse(&self) -> syn::Result<Vec<Binding>> {
+        let mut cur_mps = vec![MatcherPos {
+            idx: 0,
+            matches: Rc::new(Vec::new()),
+        }];
+        let mut pos = 0;
+
+        loop {
+            let tok = &self.input.toks[pos];
+
+            let mut next_mps = Vec::new();
+            let mut bb_mps = Vec::new();
+            let mut eof_mps = Vec::new();
+
+            while let Some(mut mp) = cur_mps.pop() {
+                match &self.locs[mp.idx] {
+                    Loc::Token(expected) => {
+                        if matches!(&tok.kind, FlatKind::Tok(t) if t == expected) {
+                            mp.idx += 1;
+                            next_mps.push(mp);
+                        }
+                    }
+                    Loc::Open(expected) => {
+                        if matches!(&tok.kind, FlatKind::Open(d) if d == expected) {
+                            mp.idx += 1;
+                            next_mps.push(mp);
+                        }
+                    }
+                    Loc::Close(expected) => {
+                        if matches!(&tok.kind, FlatKind::Close(d) if d == expected) {
+                            mp.idx += 1;
+                            next_mps.push(mp);
+                        }
+                    }
+                    &Loc::Sequence {
+                        op,
+                        num_metavar_decls,
+                        idx_first_after,
+                        next_metavar,
+                        seq_depth,
+                    } => {
+                        // install an empty seq for each metavariable within the sequence
+                        for metavar_idx in next_metavar..next_metavar + num_metavar_decls {
+                            mp.push_match(metavar_idx, seq_depth, Binding::Seq(Vec::new()));
+                        }
+
+                        if op != RepeatOp::OneOrMore {
+                            // zero times
+                            cur_mps.push(MatcherPos {
+                                idx: idx_first_after,
+                                matches: Rc::clone(&mp.matches),
+                            });
+                        }
+
+                        mp.idx += 1;
+                        cur_mps.push(mp);
+                    }
+                    &Loc::SequenceKleeneOpNoSep { op, idx_first } => {
+                        // end the sequence
+                        cur_mps.push(MatcherPos {
+                            idx: mp.idx + 1,
+                            matches: Rc::clone(&mp.matches),
+                        });
+
+                        if op != RepeatOp::ZeroOrOne {
+                            mp.idx = idx_first;
+                            cur_mps.push(mp);
+                        }
+                    }
+                    Loc::SequenceSep { separator } => {
+                        // end the sequence, skipping `SequenceKleeneOpAfterSep`
+                        cur_mps.push(MatcherPos {
+                            idx: mp.idx + 2,
+                            matches: Rc::clone(&mp.matches),
+                        });
+
+                        if matches!(&tok.kind, FlatKind::Tok(t) if t == separator) {
+                            mp.idx += 1;
+                            next_mps.push(mp);
+                        }
+                    }
+                    &Loc::SequenceKleeneOpAfterSep { idx_first } => {
+                        mp.idx = idx_first;
+                        cur_mps.push(mp);
+                    }
+                    &Loc::MetaVarDecl { kind, .. } => {
+                        if may_begin_with(kind, &tok.kind, self.edition) {
+                            bb_mps.push(mp);
+                        }
+                    }
+                    Loc::Eof => {
+                        if matches!(tok.kind, FlatKind::Eof) {
+                            eof_mps.push(mp);
+                        }
+                    }
+                }
+            }
+
+            if matches!(tok.kind, FlatKind::Eof) {
+                return match eof_mps.len() {
+                    1 => Ok(Rc::unwrap_or_clone(eof_mps.pop().unwrap().matches)),
+                    0 => Err(syn::Error::new(
+                        tok.span,
+                        "unexpected end of macro invocation",
+                    )),
+                    _ => Err(syn::Error::new(
+                        tok.span,
+                        "ambiguity: multiple successful parses",
+                    )),
+                };
+            }
+
+            match (next_mps.len(), bb_mps.len()) {
+                (0, 0) => {
+                    return Err(syn::Error::new(
+                        tok.span,
+                        format!(
+                            "no rules expected the token `{}`",
+                            flat_to_string(&tok.kind)
+                        ),
+                    ))
+                }
+                (_, 0) => {
+                    cur_mps = next_mps;
+                    pos += 1;
+                }
+                (0, 1) => {
+                    let mut mp = bb_mps.pop().unwrap();
+                    let Loc::MetaVarDecl {
+                        kind,
+                        next_metavar,
+                        seq_depth,
+                    } = self.locs[mp.idx]
+                    else {
+                        unreachable!()
+                    };
+
+                    let (fragment, next_pos) = self.parse_fragment(kind, tok)?;
+
+                    mp.push_match(next_metavar, seq_depth, Binding::Single(fragment));
+                    mp.idx += 1;
+                    cur_mps = vec![mp];
+                    pos = next_pos;
+                }
+                _ => {
+                    let names = bb_mps
+                        .iter()
+                        .filter_map(|mp| match &self.locs[mp.idx] {
+                            Loc::MetaVarDecl { kind, .. } => Some(format!("`{}`", kind)),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" or ");
+
+                    return Err(syn::Error::new(
+                        tok.span,
+                        format!(
+                            "local ambiguity when calling macro: multiple parsing options: built-in NTs {} ('{}' other options)",
+                            names,
+                            next_mps.len()
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn parse_fragment(
+        &self,
+        kind: MetaVarKind,
+        tok: &FlatTok,
+    ) -> syn::Result<(BoundFragment, usize)> {
+        let trees = &self.input.levels[tok.level][tok.index..];
+
+        let consumed = match (kind, &tok.kind) {
+            (MetaVarKind::Tt, FlatKind::Tok(t)) => t.trees.len(),
+            (MetaVarKind::Tt, _) => 1,
+            (MetaVarKind::Frag(frag), _) => match_fragment(frag, trees, self.edition)?.consumed,
+        };
+
+        let byte_range = match consumed.checked_sub(1) {
+            Some(last) => trees[0].span().byte_range().start..trees[last].span().byte_range().end,
+            None => {
+                let start = tok.span.byte_range().start;
+                start..start
+            }
+        };
+
+        let Some(next_pos) = self.input.positions[tok.level][tok.index + consumed] else {
+            return Err(syn::Error::new(
+                trees[consumed].span(),
+                format!("`{}` fragment ends in the middle of a token", kind),
+            ));
+        };
+
+        let fragment = BoundFragment {
+            tokens: trees[..consumed].iter().cloned().collect(),
+            byte_range,
+        };
+
+        Ok((fragment, next_pos))
+    }
+}
+
+fn flat_to_string(kind: &FlatKind) -> String {
+    match kind {
+        FlatKind::Tok(tok) => tok.to_string(),
+        FlatKind::Open(delim) | FlatKind::Close(delim) => {
+            let (open, close) = match delim {
+                Delimiter::Parenthesis => ("(", ")"),
+                Delimiter::Brace => ("{", "}"),
+                Delimiter::Bracket => ("[", "]"),
+                Delimiter::None => ("", ""),
+            };
+
+            if matches!(kind, FlatKind::Open(_)) {
+                open
+            } else {
+                close
+            }
+            .to_string()
+        }
+        FlatKind::Eof => "<eof>".to_string(),
+    }
+}
+
+// ref: rustc_parse::parser::nonterminal::nonterminal_may_begin_with
+fn may_begin_with(kind: MetaVarKind, tok: &FlatKind, edition: Edition) -> bool {
+    let frag = match kind {
+        MetaVarKind::Tt => return !matches!(tok, FlatKind::Close(_) | FlatKind::Eof),
+        MetaVarKind::Frag(frag) => frag,
+    };
+
+    // `vis` may be empty
+    if frag == FragSpecs::Vis {
+        return true;
+    }
+
+    let tok = match tok {
+        FlatKind::Tok(tok) => tok,
+        FlatKind::Open(delim) => {
+            return match frag {
+                FragSpecs::Block => *delim == Delimiter::Brace,
+                FragSpecs::Expr | FragSpecs::Expr2021 | FragSpecs::Item | FragSpecs::Stmt => true,
+                FragSpecs::Ty | FragSpecs::Pat | FragSpecs::PatParam => {
+                    matches!(delim, Delimiter::Parenthesis | Delimiter::Bracket)
+                }
+                _ => false,
+            }
+        }
+        FlatKind::Close(_) | FlatKind::Eof => return false,
+    };
+
+    let text = tok.text.as_str();
+
+    match (frag, tok.kind) {
+        (FragSpecs::Ident, TokKind::Ident) => text != "_",
+        (FragSpecs::Ident, _) => false,
+        (FragSpecs::Lifetime, kind) => kind == TokKind::Lifetime,
+        (FragSpecs::Literal, TokKind::Literal) => true,
+        (FragSpecs::Literal, TokKind::Ident) => text == "true" || text == "false",
+        (FragSpecs::Literal, TokKind::Punct) => text == "-",
+        (FragSpecs::Literal, _) => false,
+        (FragSpecs::Block, _) => false,
+        (FragSpecs::Path | FragSpecs::Meta, TokKind::Ident) => true,
+        (FragSpecs::Path | FragSpecs::Meta, TokKind::Punct) => text == "::",
+        (FragSpecs::Path | FragSpecs::Meta, _) => false,
+        (FragSpecs::Pat | FragSpecs::PatParam, TokKind::Ident | TokKind::Literal) => true,
+        (FragSpecs::Pat | FragSpecs::PatParam, TokKind::Punct) => {
+            ["&", "&&", "-", "..", "...", "..=", "::", "<", "<<"].contains(&text)
+                || (text == "|" && frag == FragSpecs::Pat && edition.has_or_patterns())
+        }
+        (FragSpecs::Pat | FragSpecs::PatParam, TokKind::Lifetime) => false,
+        (FragSpecs::Ty, TokKind::Ident | TokKind::Lifetime) => true,
+        (FragSpecs::Ty, TokKind::Punct) => {
+            ["!", "*", "&", "&&", "?", "<", "<<", "::"].contains(&text)
+        }
+        (FragSpecs::Ty, TokKind::Literal) => false,
+        (FragSpecs::Expr | FragSpecs::Expr2021, TokKind::Ident) => {
+            text != "let"
+                && (text != "const" || (frag == FragSpecs::Expr && edition.has_expr_2024()))
+        }
+        (FragSpecs::Expr | FragSpecs::Expr2021, TokKind::Literal | TokKind::Lifetime) => true,
+        (FragSpecs::Expr | FragSpecs::Expr2021, TokKind::Punct) => [
+            "!", "-", "*", "|", "||", "&", "&&", "..", "..=", "<", "<<", "::", "#",
+        ]
+        .contains(&text),
+        (FragSpecs::Item | FragSpecs::Stmt, _) => true,
+        (FragSpecs::Vis, _) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(arms: &str) -> syn::Result<MacroRules> {
+        MacroRules::parse(&format!("macro_rules! m {{ {} }}", arms), Edition::E2021)
+    }
+
+    fn parse_error(arms: &str) -> String {
+        rules(arms).unwrap_err().to_string()
+    }
+
+    /// Index of the matched arm, or the error of the last arm tried.
+    fn matched(arms: &str, invocation: &str) -> Result<ArmMatch, String> {
+        let m = rules(arms).unwrap().match_invocation(invocation).unwrap();
+        m.matched
+            .ok_or_else(|| m.errors.last().unwrap().error.to_string())
+    }
+
+    fn seq_len(binding: &Binding) -> usize {
+        match binding {
+            Binding::Seq(seq) => seq.len(),
+            Binding::Single(_) => panic!("expected a sequence"),
+        }
+    }
+
+    #[test]
+    fn follow_set_errors() {
+        assert!(parse_error("($e:expr +) => {}").contains("`$e:expr` is followed by `+`"));
+        assert!(parse_error("($t:ty $u:ty) => {}").contains("`$t:ty` is followed by `$_:ty`"));
+        assert!(parse_error("($($e:expr)+) => {}").contains("`$e:expr` is followed by `$_:expr`"));
+
+        assert!(rules("($e:expr, $t:ty => $p:path) => {}").is_ok());
+        assert!(rules("($($e:expr),+) => {}").is_ok());
+    }
+
+    #[test]
+    fn matcher_errors() {
+        assert!(parse_error("($x:ident $x:ident) => {}").contains("duplicate matcher binding `$x`"));
+        assert!(parse_error("($()*) => {}").contains("repetition matches empty token tree"));
+        assert!(parse_error("($x) => {}").contains("missing fragment specifier"));
+        assert!(parse_error("($x:foo) => {}").contains("foo"));
+    }
+
+    #[test]
+    fn arms_are_tried_in_order() {
+        let arms = "($i:ident) => {}; ($e:expr) => {}";
+
+        assert_eq!(matched(arms, "a").unwrap().arm, 0);
+        assert_eq!(matched(arms, "a + 1").unwrap().arm, 1);
+        assert!(matched(arms, "a b").is_err());
+    }
+
+    #[test]
+    fn local_ambiguity() {
+        let arms = "($($i:ident)* $j:ident) => {}";
+
+        assert!(matched(arms, "a b")
+            .unwrap_err()
+            .contains("local ambiguity when calling macro"));
+    }
+
+    #[test]
+    fn no_rules_expected_the_token() {
+        let arms = "(a $e:expr) => {}";
+
+        assert!(matched(arms, "b 1")
+            .unwrap_err()
+            .contains("no rules expected the token `b`"));
+        assert!(matched(arms, "a")
+            .unwrap_err()
+            .contains("unexpected end of macro invocation"));
+    }
+
+    #[test]
+    fn nested_repetitions() {
+        let arms = "($($k:ident: [$($v:expr),*]);*) => {}";
+        let m = matched(arms, "a: [1, 2]; b: []; c: [3]").unwrap();
+
+        assert_eq!(seq_len(&m.bindings[0].binding), 3);
+        let Binding::Seq(values) = &m.bindings[1].binding else {
+            panic!("expected a sequence");
+        };
+        assert_eq!(values.iter().map(seq_len).collect::<Vec<_>>(), [2, 0, 1]);
+    }
+
+    #[test]
+    fn optional_trailing_comma() {
+        let arms = "($($e:expr),* $(,)?) => {}";
+
+        for invocation in ["", "1", "1, 2", "1, 2,"] {
+            assert!(matched(arms, invocation).is_ok(), "{}", invocation);
+        }
+        assert!(matched(arms, "1,,").is_err());
+    }
+
+    #[test]
+    fn question_mark_after_a_repetition_is_the_operator() {
+        let arms = "($(a)?*) => {}";
+
+        assert!(matched(arms, "a*").is_ok());
+        assert!(matched(arms, "*").is_ok());
+        assert!(parse_error("($(a),?) => {}").contains("expected one of: `*` or `+`"));
+    }
+
+    #[test]
+    fn invocation_of_the_macro_itself_is_unwrapped() {
+        let arms = "($i:ident) => {}";
+
+        assert!(matched(arms, "m!(a)").is_ok());
+        assert!(matched(arms, "crate::m![a]").is_ok());
+        assert!(matched(arms, "a").is_ok());
+
+        let error = rules(arms).unwrap().match_invocation("n!(a)").unwrap_err();
+        assert_eq!(error.to_string(), "`n!` is not an invocation of `m!`");
+    }
+
+    #[test]
+    fn ranges_of_the_bindings() {
+        let m = matched("($a:ident + $b:literal) => {}", "x + 12").unwrap();
+        let ranges = m.ranges();
+
+        assert_eq!(ranges[&FragSpecs::Ident], vec![0..1]);
+        assert_eq!(ranges[&FragSpecs::Literal], vec![4..6]);
+    }
+}
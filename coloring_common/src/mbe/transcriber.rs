@@ -0,0 +1,434 @@
+use super::macro_rules::{
+    parse_repeat_op, Binding, BoundFragment, MetaVarKind, NamedBinding, RepeatOp, Tok,
+};
+use crate::FragSpecs;
+use proc_macro2::{Delimiter, Group, Ident, Punct, Span, TokenStream, TokenTree};
+use std::ops::Range;
+
+// ref: https://github.com/rust-lang/rust/blob/master/compiler/rustc_expand/src/mbe/transcribe.rs
+
+#[derive(Debug, Clone)]
+pub(super) enum TranscriberTree {
+    Token(Tok),
+    Group(Group, Vec<TranscriberTree>),
+    MetaVar {
+        dollar: Punct,
+        name: Ident,
+    },
+    Repeat {
+        trees: Vec<TranscriberTree>,
+        separator: Option<Tok>,
+        op: RepeatOp,
+        span: Span,
+    },
+}
+
+pub(super) fn parse_transcriber(trees: &[TokenTree]) -> syn::Result<Vec<TranscriberTree>> {
+    let mut res = Vec::new();
+    let mut i = 0;
+
+    while i < trees.len() {
+        match (&trees[i], trees.get(i + 1)) {
+            (TokenTree::Punct(dollar), Some(TokenTree::Ident(name))) if dollar.as_char() == '$' => {
+                res.push(TranscriberTree::MetaVar {
+                    dollar: dollar.clone(),
+                    name: name.clone(),
+                });
+                i += 2;
+            }
+            (TokenTree::Punct(dollar), Some(TokenTree::Group(group)))
+                if dollar.as_char() == '$' && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                let inner = parse_transcriber(&group.stream().into_iter().collect::<Vec<_>>())?;
+                let (separator, op, width) = parse_repeat_op(&trees[i + 2..], group.span())?;
+
+                res.push(TranscriberTree::Repeat {
+                    trees: inner,
+                    separator,
+                    op,
+                    span: group.span(),
+                });
+                i += 2 + width;
+            }
+            (TokenTree::Group(group), _) => {
+                let inner = parse_transcriber(&group.stream().into_iter().collect::<Vec<_>>())?;
+
+                res.push(TranscriberTree::Group(group.clone(), inner));
+                i += 1;
+            }
+            _ => {
+                let tok = Tok::glue(&trees[i..]).unwrap();
+
+                i += tok.trees().len();
+                res.push(TranscriberTree::Token(tok));
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Where an emitted token came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenOrigin {
+    /// A token written in the transcriber. Byte range in the definition.
+    Transcriber(Range<usize>),
+    /// A token bound to a metavariable. Byte range in the invocation.
+    MetaVar {
+        name: String,
+        byte_range: Range<usize>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpandedToken {
+    pub text: String,
+    pub origin: TokenOrigin,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub tokens: TokenStream,
+    /// Every emitted token (including delimiters) in order.
+    pub annotated: Vec<ExpandedToken>,
+}
+
+pub(super) fn transcribe(
+    trees: &[TranscriberTree],
+    bindings: &[NamedBinding],
+) -> syn::Result<Expansion> {
+    let mut transcriber = Transcriber {
+        bindings,
+        repeats: Vec::new(),
+        annotated: Vec::new(),
+    };
+
+    let tokens = transcriber.transcribe(trees)?.into_iter().collect();
+
+    Ok(Expansion {
+        tokens,
+        annotated: transcriber.annotated,
+    })
+}
+
+struct Transcriber<'a> {
+    bindings: &'a [NamedBinding],
+    /// Index of the current iteration of each enclosing repetition.
+    repeats: Vec<usize>,
+    annotated: Vec<ExpandedToken>,
+}
+
+enum LockstepLen {
+    Unconstrained,
+    Constraint(usize, String),
+}
+
+impl<'a> Transcriber<'a> {
+    fn transcribe(&mut self, trees: &[TranscriberTree]) -> syn::Result<Vec<TokenTree>> {
+        let mut res = Vec::new();
+
+        for tree in trees {
+            match tree {
+                TranscriberTree::Token(tok) => {
+                    self.annotate_transcriber(&tok.to_string(), tok.byte_range());
+                    res.extend(tok.trees().iter().cloned());
+                }
+                TranscriberTree::Group(group, inner) => {
+                    let (open, close) = delimiter_texts(group.delimiter());
+
+                    self.annotate_transcriber(open, group.span_open().byte_range());
+                    let stream = self.transcribe(inner)?.into_iter().collect();
+                    self.annotate_transcriber(close, group.span_close().byte_range());
+
+                    let mut new_group = Group::new(group.delimiter(), stream);
+                    new_group.set_span(group.span());
+                    res.push(TokenTree::Group(new_group));
+                }
+                TranscriberTree::MetaVar { dollar, name } => {
+                    res.extend(self.transcribe_metavar(dollar, name)?);
+                }
+                TranscriberTree::Repeat {
+                    trees,
+                    separator,
+                    op,
+                    span,
+                } => {
+                    let len = match self.lockstep_len(trees)? {
+                        LockstepLen::Constraint(len, _) => len,
+                        LockstepLen::Unconstrained => {
+                            return Err(syn::Error::new(
+                                *span,
+                                "attempted to repeat an expression containing no syntax variables matched as repeating at this depth",
+                            ))
+                        }
+                    };
+
+                    if len == 0 && *op == RepeatOp::OneOrMore {
+                        return Err(syn::Error::new(*span, "this must repeat at least once"));
+                    }
+
+                    for i in 0..len {
+                        if i > 0 {
+                            if let Some(separator) = separator {
+                                self.annotate_transcriber(
+                                    &separator.to_string(),
+                                    separator.byte_range(),
+                                );
+                                res.extend(separator.trees().iter().cloned());
+                            }
+                        }
+
+                        self.repeats.push(i);
+                        let inner = self.transcribe(trees);
+                        self.repeats.pop();
+
+                        res.extend(inner?);
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn transcribe_metavar(&mut self, dollar: &Punct, name: &Ident) -> syn::Result<Vec<TokenTree>> {
+        let Some((kind, binding)) = self.lookup(name) else {
+            // not a metavariable, e.g. `$crate`
+            let tokens = vec![
+                TokenTree::Punct(dollar.clone()),
+                TokenTree::Ident(name.clone()),
+            ];
+
+            let range = dollar.span().byte_range().start..name.span().byte_range().end;
+            self.annotate_transcriber(&format!("${}", name), range);
+
+            return Ok(tokens);
+        };
+
+        let BoundFragment { tokens, .. } = match binding {
+            Binding::Single(fragment) => fragment,
+            Binding::Seq(_) => {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("variable `{}` is still repeating at this depth", name),
+                ))
+            }
+        };
+
+        let trees = tokens.clone().into_iter().collect::<Vec<_>>();
+        annotate_fragment(&trees, &name.to_string(), &mut self.annotated);
+
+        let res = match kind {
+            // rustc wraps parsed fragments with invisible delimiters to keep their precedence
+            MetaVarKind::Frag(frag) if !matches!(frag, FragSpecs::Ident | FragSpecs::Lifetime) => {
+                let mut group = Group::new(Delimiter::None, tokens.clone());
+                group.set_span(name.span());
+
+                vec![TokenTree::Group(group)]
+            }
+            _ => trees,
+        };
+
+        Ok(res)
+    }
+
+    /// Looks up the binding of `name` for the current iterations of the enclosing repetitions.
+    fn lookup(&self, name: &Ident) -> Option<(MetaVarKind, &'a Binding)> {
+        let named = self.bindings.iter().find(|b| name == b.name.as_str())?;
+
+        let mut binding = &named.binding;
+        for &i in &self.repeats {
+            match binding {
+                Binding::Seq(seq) => binding = seq.get(i)?,
+                Binding::Single(_) => break,
+            }
+        }
+
+        Some((named.kind, binding))
+    }
+
+    fn lockstep_len(&self, trees: &[TranscriberTree]) -> syn::Result<LockstepLen> {
+        let mut res = LockstepLen::Unconstrained;
+
+        for tree in trees {
+            let len = match tree {
+                TranscriberTree::Token(_) => LockstepLen::Unconstrained,
+                TranscriberTree::Group(_, trees) | TranscriberTree::Repeat { trees, .. } => {
+                    self.lockstep_len(trees)?
+                }
+                TranscriberTree::MetaVar { name, .. } => match self.lookup(name) {
+                    Some((_, Binding::Seq(seq))) => {
+                        LockstepLen::Constraint(seq.len(), name.to_string())
+                    }
+                    _ => LockstepLen::Unconstrained,
+                },
+            };
+
+            res = match (res, len) {
+                (LockstepLen::Unconstrained, len) | (len, LockstepLen::Unconstrained) => len,
+                (LockstepLen::Constraint(l1, n1), LockstepLen::Constraint(l2, n2)) => {
+                    if l1 != l2 {
+                        let span = match tree {
+                            TranscriberTree::MetaVar { name, .. } => name.span(),
+                            TranscriberTree::Repeat { span, .. } => *span,
+                            TranscriberTree::Group(group, _) => group.span(),
+                            TranscriberTree::Token(tok) => tok.span(),
+                        };
+
+                        return Err(syn::Error::new(
+                            span,
+                            format!(
+                                "meta-variable `{}` repeats {} times, but `{}` repeats {} times",
+                                n1, l1, n2, l2
+                            ),
+                        ));
+                    }
+
+                    LockstepLen::Constraint(l1, n1)
+                }
+            };
+        }
+
+        Ok(res)
+    }
+
+    fn annotate_transcriber(&mut self, text: &str, byte_range: Range<usize>) {
+        self.annotated.push(ExpandedToken {
+            text: text.to_string(),
+            origin: TokenOrigin::Transcriber(byte_range),
+        });
+    }
+}
+
+fn annotate_fragment(trees: &[TokenTree], name: &str, annotated: &mut Vec<ExpandedToken>) {
+    let token = |text: &str, byte_range: Range<usize>| ExpandedToken {
+        text: text.to_string(),
+        origin: TokenOrigin::MetaVar {
+            name: name.to_string(),
+            byte_range,
+        },
+    };
+
+    let mut i = 0;
+    while i < trees.len() {
+        match &trees[i] {
+            TokenTree::Group(group) => {
+                let (open, close) = delimiter_texts(group.delimiter());
+                let inner = group.stream().into_iter().collect::<Vec<_>>();
+
+                annotated.push(token(open, group.span_open().byte_range()));
+                annotate_fragment(&inner, name, annotated);
+                annotated.push(token(close, group.span_close().byte_range()));
+                i += 1;
+            }
+            _ => {
+                let tok = Tok::glue(&trees[i..]).unwrap();
+
+                annotated.push(token(&tok.to_string(), tok.byte_range()));
+                i += tok.trees().len();
+            }
+        }
+    }
+}
+
+fn delimiter_texts(delimiter: Delimiter) -> (&'static str, &'static str) {
+    match delimiter {
+        Delimiter::Parenthesis => ("(", ")"),
+        Delimiter::Brace => ("{", "}"),
+        Delimiter::Bracket => ("[", "]"),
+        Delimiter::None => ("", ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mbe::MacroRules;
+    use crate::Edition;
+
+    /// Texts of the emitted tokens joined with spaces, or the error of the transcription.
+    fn expand(arms: &str, invocation: &str) -> Result<String, String> {
+        let rules =
+            MacroRules::parse(&format!("macro_rules! m {{ {} }}", arms), Edition::E2021).unwrap();
+        let arm_match = rules.match_invocation(invocation).unwrap().matched.unwrap();
+
+        rules
+            .transcribe(&arm_match)
+            .map(|expansion| {
+                expansion
+                    .annotated
+                    .iter()
+                    .map(|token| token.text.as_str())
+                    .filter(|text| !text.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn repeats_in_lockstep() {
+        let arms = "($($k:ident = $v:expr),*) => { $($k: $v);* }";
+
+        assert_eq!(expand(arms, "a = 1, b = 2").unwrap(), "a : 1 ; b : 2");
+        assert_eq!(expand(arms, "").unwrap(), "");
+    }
+
+    #[test]
+    fn lockstep_length_mismatch() {
+        let arms = "($($a:ident)*; $($b:ident)*) => { $(($a $b))* }";
+
+        assert_eq!(
+            expand(arms, "x y; z").unwrap_err(),
+            "meta-variable `a` repeats 2 times, but `b` repeats 1 times"
+        );
+        assert_eq!(expand(arms, "x; z").unwrap(), "( x z )");
+    }
+
+    #[test]
+    fn repetition_without_repeating_variables() {
+        let arms = "($a:ident) => { $($a)* }";
+
+        assert!(expand(arms, "x")
+            .unwrap_err()
+            .starts_with("attempted to repeat an expression containing no syntax variables"));
+    }
+
+    #[test]
+    fn variable_still_repeating() {
+        let arms = "($($a:ident)*) => { $a }";
+
+        assert_eq!(
+            expand(arms, "x y").unwrap_err(),
+            "variable `a` is still repeating at this depth"
+        );
+    }
+
+    #[test]
+    fn one_or_more_with_no_repetitions() {
+        let arms = "($($a:ident)*) => { $($a)+ }";
+
+        assert_eq!(expand(arms, "x").unwrap(), "x");
+        assert_eq!(
+            expand(arms, "").unwrap_err(),
+            "this must repeat at least once"
+        );
+    }
+
+    #[test]
+    fn nested_repetitions_and_outer_variables() {
+        let arms = "($f:ident: $($k:ident [$($v:literal)*])*) => { $($($f($k, $v);)*)* }";
+
+        assert_eq!(
+            expand(arms, "g: a [1 2] b [3]").unwrap(),
+            "g ( a , 1 ) ; g ( a , 2 ) ; g ( b , 3 ) ;"
+        );
+    }
+
+    #[test]
+    fn non_metavariables_are_kept() {
+        assert_eq!(
+            expand("() => { $crate::f() }", "").unwrap(),
+            "$crate :: f ( )"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{FragNode, FragRange, FragSpecs};
+
+const SNIPPET_LEN: usize = 40;
+
+/// One line per node, indented by its depth.
+/// `style` decorates the snippet of each node by its fragments, e.g. with colors.
+///
+/// ```text
+/// ExprCall [expr_2021, expr] 1:0-1:4 `f(1)`
+///   Path [path] 1:0-1:1 `f`
+/// ```
+pub fn outline(
+    content: &str,
+    tree: &[FragNode],
+    style: impl Fn(&[FragSpecs], String) -> String,
+) -> String {
+    let mut lines = Vec::new();
+
+    for root in tree {
+        root.walk(&mut |node, depth| {
+            let FragNode {
+                frags, kind, range, ..
+            } = node;
+            let FragRange {
+                byte, start, end, ..
+            } = range;
+
+            let frags_str = frags
+                .iter()
+                .map(|f| f.to_string().to_ascii_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            lines.push(format!(
+                "{}{} [{}] {}:{}-{}:{} `{}`",
+                "  ".repeat(depth),
+                kind,
+                frags_str,
+                start.line,
+                start.column,
+                end.line,
+                end.column,
+                style(frags, snippet(&content[byte.clone()])),
+            ));
+        });
+    }
+
+    lines.join("\n")
+}
+
+fn snippet(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if text.chars().count() <= SNIPPET_LEN {
+        return text;
+    }
+
+    let mut res = text.chars().take(SNIPPET_LEN - 3).collect::<String>();
+    res.push_str("...");
+
+    res
+}
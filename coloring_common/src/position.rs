@@ -0,0 +1,149 @@
+use crate::{FragSpecs, RangeMap};
+use proc_macro2::{LineColumn, Span};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+
+pub type FragRangeMap = HashMap<FragSpecs, Vec<FragRange>>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "LineColumn")]
+struct LineColumnDef {
+    line: usize,
+    column: usize,
+}
+
+/// Where a fragment is in the source.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct FragRange {
+    /// Byte offsets.
+    pub byte: Range<usize>,
+    /// 1-based line and 0-based column counted in chars.
+    #[serde(with = "LineColumnDef")]
+    pub start: LineColumn,
+    #[serde(with = "LineColumnDef")]
+    pub end: LineColumn,
+    /// Offsets counted in UTF-16 code units, which is what JavaScript strings are indexed by.
+    pub utf16: Range<usize>,
+}
+
+impl FragRange {
+    pub fn new(span: Span, utf16: &Utf16Index) -> Self {
+        let byte = span.byte_range();
+
+        Self {
+            utf16: utf16.offset(byte.start)..utf16.offset(byte.end),
+            byte,
+            start: span.start(),
+            end: span.end(),
+        }
+    }
+}
+
+/// Converts byte offsets of a source into UTF-16 offsets.
+///
+/// The default one has no source and treats it as ASCII.
+#[derive(Debug, Clone, Default)]
+pub struct Utf16Index {
+    /// UTF-16 offset of each byte offset ( `source.len() + 1` entries ).
+    offsets: Vec<usize>,
+}
+
+impl Utf16Index {
+    pub fn new(source: &str) -> Self {
+        let mut offsets = Vec::with_capacity(source.len() + 1);
+        let mut utf16 = 0;
+
+        for c in source.chars() {
+            offsets.extend(std::iter::repeat_n(utf16, c.len_utf8()));
+            utf16 += c.len_utf16();
+        }
+        offsets.push(utf16);
+
+        Self { offsets }
+    }
+
+    /// Inverse of [`Utf16Index::offset`] . Returns `None` for the middle of a char.
+    pub fn byte_offset(&self, utf16: usize) -> Option<usize> {
+        if self.offsets.is_empty() {
+            return Some(utf16);
+        }
+
+        let byte = self.offsets.partition_point(|&offset| offset < utf16);
+
+        // the first byte of a char is the first one having the offset
+        (self.offsets.get(byte) == Some(&utf16)).then_some(byte)
+    }
+
+    pub fn offset(&self, byte: usize) -> usize {
+        match self.offsets.last() {
+            Some(&last) => self.offsets.get(byte).copied().unwrap_or(last),
+            None => byte,
+        }
+    }
+}
+
+pub(crate) fn byte_ranges(ranges: &FragRangeMap) -> RangeMap {
+    ranges
+        .iter()
+        .map(|(frag, ranges)| (*frag, ranges.iter().map(|r| r.byte.clone()).collect()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Edition, WholeMatcher};
+
+    #[test]
+    fn utf16_offsets() {
+        // `😀` is 4 bytes and a surrogate pair, `é` is 2 bytes and 1 code unit
+        let index = Utf16Index::new("a😀é");
+
+        let offsets = (0..=7).map(|byte| index.offset(byte)).collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 1, 1, 1, 1, 3, 3, 4]);
+        assert_eq!(index.offset(100), 4);
+
+        assert_eq!(index.byte_offset(0), Some(0));
+        assert_eq!(index.byte_offset(1), Some(1));
+        assert_eq!(index.byte_offset(2), None);
+        assert_eq!(index.byte_offset(3), Some(5));
+        assert_eq!(index.byte_offset(4), Some(7));
+        assert_eq!(index.byte_offset(5), None);
+    }
+
+    #[test]
+    fn default_index_is_ascii() {
+        let index = Utf16Index::default();
+
+        assert_eq!(index.offset(3), 3);
+        assert_eq!(index.byte_offset(3), Some(3));
+    }
+
+    #[test]
+    fn frag_range_positions() {
+        let content = "\"😀\" +\n  x";
+        let top_match = WholeMatcher::Expr
+            .try_parse(content, Edition::E2021)
+            .unwrap();
+
+        assert_eq!(
+            top_match.ranges[&FragSpecs::Ident],
+            [FragRange {
+                byte: 11..12,
+                start: LineColumn { line: 2, column: 2 },
+                end: LineColumn { line: 2, column: 3 },
+                utf16: 9..10,
+            }]
+        );
+        assert_eq!(
+            top_match.ranges[&FragSpecs::Literal],
+            [FragRange {
+                byte: 0..6,
+                start: LineColumn { line: 1, column: 0 },
+                end: LineColumn { line: 1, column: 3 },
+                utf16: 0..4,
+            }]
+        );
+    }
+}
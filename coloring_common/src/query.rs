@@ -0,0 +1,213 @@
+use crate::{
+    classify, Edition, FragNode, FragRange, FragSpecs, TopMatch, Utf16Index, WholeMatcher,
+};
+use proc_macro2::LineColumn;
+use serde::{Deserialize, Serialize};
+
+/// A cursor position in the code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Offset {
+    Byte {
+        byte: usize,
+    },
+    /// Offset in UTF-16 code units, which is what JavaScript strings are indexed by.
+    Utf16 {
+        utf16: usize,
+    },
+    /// 1-based line and 0-based column counted in chars.
+    LineColumn {
+        line: usize,
+        column: usize,
+    },
+}
+
+impl Offset {
+    /// Returns `None` when it is out of `content` .
+    pub fn to_byte(self, content: &str) -> Option<usize> {
+        match self {
+            Offset::Byte { byte } => content.is_char_boundary(byte).then_some(byte),
+            Offset::Utf16 { utf16 } => Utf16Index::new(content).byte_offset(utf16),
+            Offset::LineColumn { line, column } => {
+                byte_offset(content, LineColumn { line, column })
+            }
+        }
+    }
+}
+
+fn byte_offset(content: &str, LineColumn { line, column }: LineColumn) -> Option<usize> {
+    let line_start = match line.checked_sub(1)? {
+        0 => 0,
+        n => content.match_indices('\n').nth(n - 1)?.0 + 1,
+    };
+    let line_content = content[line_start..].split('\n').next().unwrap();
+    // the `\r` of CRLF is not a column
+    let line_content = line_content.strip_suffix('\r').unwrap_or(line_content);
+
+    match line_content.char_indices().nth(column) {
+        Some((i, _)) => Some(line_start + i),
+        // just after the last char of the line
+        None if line_content.chars().count() == column => Some(line_start + line_content.len()),
+        None => None,
+    }
+}
+
+/// A fragment specifier which a node containing the cursor satisfies.
+#[derive(Debug, Clone, Serialize)]
+pub struct FragmentAt {
+    pub frag: FragSpecs,
+    pub kind: &'static str,
+    pub range: FragRange,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FragmentsAt {
+    /// The matcher which the whole code is parsed as.
+    pub matcher: WholeMatcher,
+    /// Innermost first.
+    pub stack: Vec<FragmentAt>,
+}
+
+/// Fragments containing `byte` in `tree` , innermost first.
+pub fn stack_at(tree: &[FragNode], byte: usize) -> Vec<FragmentAt> {
+    let mut path = Vec::new();
+    let mut nodes = tree;
+
+    while let Some(node) = nodes.iter().find(|node| node.range.byte.contains(&byte)) {
+        path.push(node);
+        nodes = &node.children;
+    }
+
+    path.into_iter()
+        .rev()
+        .flat_map(|node| {
+            node.frags.iter().map(|&frag| FragmentAt {
+                frag,
+                kind: node.kind,
+                range: node.range.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Answers which fragment specifiers the token sequences around `offset` satisfy.
+///
+/// Returns `None` when `offset` is out of `content` or no matcher accepts the whole `content` .
+pub fn fragments_at(
+    content: &str,
+    offset: Offset,
+    matchers: &[WholeMatcher],
+    edition: Edition,
+) -> Option<FragmentsAt> {
+    let byte = offset.to_byte(content)?;
+
+    let TopMatch { matcher, tree, .. } = classify(content, matchers, edition).top_match?;
+
+    Some(FragmentsAt {
+        matcher,
+        stack: stack_at(&tree, byte),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(content: &str, byte: usize) -> Vec<(FragSpecs, &'static str, std::ops::Range<usize>)> {
+        let tree = WholeMatcher::Expr
+            .try_parse(content, Edition::E2021)
+            .unwrap()
+            .tree;
+
+        stack_at(&tree, byte)
+            .into_iter()
+            .map(|FragmentAt { frag, kind, range }| (frag, kind, range.byte))
+            .collect()
+    }
+
+    #[test]
+    fn innermost_first() {
+        assert_eq!(
+            stack("f(x)", 2),
+            [
+                (FragSpecs::Ident, "Ident", 2..3),
+                (FragSpecs::Path, "Path", 2..3),
+                (FragSpecs::Expr2021, "ExprPath", 2..3),
+                (FragSpecs::Expr, "ExprPath", 2..3),
+                (FragSpecs::Expr2021, "ExprCall", 0..4),
+                (FragSpecs::Expr, "ExprCall", 0..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges_are_half_open() {
+        // the start of `f` , the end of `f` and the end of the whole call
+        assert_eq!(stack("f(x)", 0).first().map(|f| f.2.clone()), Some(0..1));
+        assert_eq!(
+            stack("f(x)", 1),
+            [
+                (FragSpecs::Expr2021, "ExprCall", 0..4),
+                (FragSpecs::Expr, "ExprCall", 0..4),
+            ]
+        );
+        assert_eq!(stack("f(x)", 4), []);
+    }
+
+    #[test]
+    fn offsets_to_bytes() {
+        let content = "\"é\" +\r\n  x";
+
+        assert_eq!(Offset::Byte { byte: 2 }.to_byte(content), None);
+        assert_eq!(Offset::Byte { byte: 3 }.to_byte(content), Some(3));
+        assert_eq!(Offset::Utf16 { utf16: 3 }.to_byte(content), Some(4));
+        assert_eq!(
+            Offset::LineColumn { line: 1, column: 5 }.to_byte(content),
+            Some(6)
+        );
+        assert_eq!(
+            Offset::LineColumn { line: 1, column: 6 }.to_byte(content),
+            None
+        );
+        assert_eq!(
+            Offset::LineColumn { line: 2, column: 2 }.to_byte(content),
+            Some(10)
+        );
+        assert_eq!(
+            Offset::LineColumn { line: 2, column: 3 }.to_byte(content),
+            Some(11)
+        );
+        assert_eq!(
+            Offset::LineColumn { line: 3, column: 0 }.to_byte(content),
+            None
+        );
+        assert_eq!(
+            Offset::LineColumn { line: 0, column: 0 }.to_byte(content),
+            None
+        );
+    }
+
+    #[test]
+    fn fragments_at_a_line_column() {
+        let FragmentsAt { matcher, stack } = fragments_at(
+            "1 +\r\n  x",
+            Offset::LineColumn { line: 2, column: 2 },
+            &WholeMatcher::DEFAULT_ORDER,
+            Edition::E2021,
+        )
+        .unwrap();
+
+        assert_eq!(matcher, WholeMatcher::Expr);
+        assert_eq!(stack[0].frag, FragSpecs::Ident);
+        assert_eq!(stack[0].range.byte, 7..8);
+        assert_eq!(stack[0].range.start, LineColumn { line: 2, column: 2 });
+
+        assert!(fragments_at(
+            "1 +",
+            Offset::Byte { byte: 0 },
+            &WholeMatcher::DEFAULT_ORDER,
+            Edition::E2021
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,536 @@
+use crate::{
+    classify, Edition, FragRange, FragRangeMap, FragSpecs, RangeMap, TopMatch, WholeMatcher,
+};
+use proc_macro2::LineColumn;
+use std::ops::Range;
+
+/// How many following pieces a broken piece is tried to be joined with.
+const MAX_JOIN: usize = 8;
+
+/// A part of broken code.
+#[derive(Debug, Clone)]
+pub enum Piece {
+    Parsed {
+        range: Range<usize>,
+        matcher: WholeMatcher,
+        /// Positions in the whole content.
+        ranges: FragRangeMap,
+    },
+    Failed {
+        range: Range<usize>,
+        /// The matcher which got the furthest.
+        matcher: WholeMatcher,
+        message: String,
+        /// Where the error is, in the whole content.
+        error_range: Range<usize>,
+    },
+}
+
+impl Piece {
+    pub fn range(&self) -> &Range<usize> {
+        match self {
+            Piece::Parsed { range, .. } | Piece::Failed { range, .. } => range,
+        }
+    }
+}
+
+/// Result of [`recover`] . Pieces are in order and never overlap.
+#[derive(Debug, Clone)]
+pub struct Recovered {
+    pub pieces: Vec<Piece>,
+}
+
+impl Recovered {
+    /// Byte ranges of all the parsed pieces.
+    pub fn byte_ranges(&self) -> RangeMap {
+        let mut res = RangeMap::new();
+
+        for piece in &self.pieces {
+            if let Piece::Parsed { ranges, .. } = piece {
+                for (frag, ranges) in ranges {
+                    res.entry(*frag)
+                        .or_default()
+                        .extend(ranges.iter().map(|r| r.byte.clone()));
+                }
+            }
+        }
+
+        res
+    }
+
+    pub fn retain_frags(&mut self, f: impl Fn(FragSpecs) -> bool) {
+        for piece in &mut self.pieces {
+            if let Piece::Parsed { ranges, .. } = piece {
+                ranges.retain(|frag, _| f(*frag));
+            }
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.pieces
+            .iter()
+            .any(|piece| matches!(piece, Piece::Failed { .. }))
+    }
+}
+
+/// Splits `content` at top-level items/statements and classifies each piece,
+/// so that broken code is highlighted except the broken parts.
+pub fn recover(content: &str, matchers: &[WholeMatcher], edition: Edition) -> Recovered {
+    let chunks = split_pieces(content);
+
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < chunks.len() {
+        let joined = (i..chunks.len().min(i + 1 + MAX_JOIN)).find_map(|j| {
+            let range = chunks[i].start..chunks[j].end;
+            let top_match = classify(&content[range.clone()], matchers, edition).top_match?;
+
+            Some((j, range, top_match))
+        });
+
+        match joined {
+            Some((
+                j,
+                range,
+                TopMatch {
+                    matcher, ranges, ..
+                },
+            )) => {
+                let base = Base::new(content, range.start);
+                let ranges = ranges
+                    .into_iter()
+                    .map(|(frag, ranges)| {
+                        (frag, ranges.into_iter().map(|r| base.shift(r)).collect())
+                    })
+                    .collect();
+
+                pieces.push(Piece::Parsed {
+                    range,
+                    matcher,
+                    ranges,
+                });
+                i = j + 1;
+            }
+            None => {
+                let range = chunks[i].clone();
+                pieces.push(failed_piece(content, range, matchers, edition));
+                i += 1;
+            }
+        }
+    }
+
+    Recovered { pieces }
+}
+
+fn failed_piece(
+    content: &str,
+    range: Range<usize>,
+    matchers: &[WholeMatcher],
+    edition: Edition,
+) -> Piece {
+    let text = &content[range.clone()];
+
+    // the error of the lexer tells nothing about where it is
+    if edition.tokenize(text).is_err() {
+        if let Some((message, error_range)) = unbalanced_delimiter(text) {
+            return Piece::Failed {
+                error_range: range.start + error_range.start..range.start + error_range.end,
+                range,
+                matcher: matchers.first().copied().unwrap_or(WholeMatcher::File),
+                message,
+            };
+        }
+    }
+
+    let errors = classify(text, matchers, edition).errors;
+
+    // the error which got the furthest is the most relevant one
+    let best = errors
+        .into_iter()
+        .rev()
+        .max_by_key(|e| e.error.span().byte_range().start);
+
+    match best {
+        Some(best) => {
+            let error_range = best.error.span().byte_range();
+            let error_range = if error_range.is_empty() {
+                // e.g. unexpected end of input
+                range.clone()
+            } else {
+                range.start + error_range.start..range.start + error_range.end
+            };
+
+            Piece::Failed {
+                range,
+                matcher: best.matcher,
+                message: best.error.to_string(),
+                error_range,
+            }
+        }
+        None => Piece::Failed {
+            error_range: range.clone(),
+            range,
+            matcher: WholeMatcher::File,
+            message: "no matcher is given".to_string(),
+        },
+    }
+}
+
+/// Position of a piece in the whole content.
+struct Base {
+    byte: usize,
+    line: usize,
+    column: usize,
+    utf16: usize,
+}
+
+impl Base {
+    fn new(content: &str, byte: usize) -> Self {
+        let before = &content[..byte];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        Self {
+            byte,
+            line: before.matches('\n').count(),
+            column: before[line_start..].chars().count(),
+            utf16: before.encode_utf16().count(),
+        }
+    }
+
+    fn shift(&self, range: FragRange) -> FragRange {
+        let shift_lc = |LineColumn { line, column }: LineColumn| LineColumn {
+            column: if line == 1 {
+                column + self.column
+            } else {
+                column
+            },
+            line: line + self.line,
+        };
+
+        FragRange {
+            byte: range.byte.start + self.byte..range.byte.end + self.byte,
+            start: shift_lc(range.start),
+            end: shift_lc(range.end),
+            utf16: range.utf16.start + self.utf16..range.utf16.end + self.utf16,
+        }
+    }
+}
+
+/// Splits at `;` and `}` of the top level. A line starting at column 0 also starts a new piece,
+/// since it is likely to be a new item after an unclosed one.
+/// Returned ranges are trimmed and never blank.
+fn split_pieces(content: &str) -> Vec<Range<usize>> {
+    let bytes = content.as_bytes();
+    let mut boundaries = vec![0];
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let at_line_start = i == 0 || bytes[i - 1] == b'\n';
+
+        if at_line_start
+            && depth > 0
+            && !bytes[i].is_ascii_whitespace()
+            && !b")]}".contains(&bytes[i])
+        {
+            boundaries.push(i);
+            depth = 0;
+        }
+
+        if let Some(next) = skip_non_code(content, i) {
+            i = next;
+            continue;
+        }
+
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b'}' => {
+                depth = depth.saturating_sub(1);
+
+                if depth == 0 && !continues(&content[i + 1..]) {
+                    boundaries.push(i + 1);
+                }
+            }
+            b';' if depth == 0 => boundaries.push(i + 1),
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    boundaries.push(bytes.len());
+
+    boundaries
+        .windows(2)
+        .filter_map(|w| {
+            let piece = &content[w[0]..w[1]];
+            let start = w[0] + (piece.len() - piece.trim_start().len());
+            let end = start + piece.trim().len();
+
+            (start < end).then_some(start..end)
+        })
+        .collect()
+}
+
+/// The delimiter which keeps `content` from being split into tokens, and where it is.
+fn unbalanced_delimiter(content: &str) -> Option<(String, Range<usize>)> {
+    let bytes = content.as_bytes();
+    let mut opened = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(next) = skip_non_code(content, i) {
+            i = next;
+            continue;
+        }
+
+        match bytes[i] {
+            b'(' | b'[' | b'{' => opened.push(i),
+            close @ (b')' | b']' | b'}') => {
+                let close = close as char;
+
+                match opened.pop().map(|o| bytes[o] as char) {
+                    Some(open) if closing_delimiter(open) == close => {}
+                    Some(open) => {
+                        return Some((
+                            format!("mismatched closing delimiter `{}` for `{}`", close, open),
+                            i..i + 1,
+                        ))
+                    }
+                    None => {
+                        return Some((
+                            format!("unexpected closing delimiter `{}`", close),
+                            i..i + 1,
+                        ))
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    let open = opened.pop()?;
+    Some((
+        format!("unclosed delimiter `{}`", bytes[open] as char),
+        open..open + 1,
+    ))
+}
+
+fn closing_delimiter(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }
+}
+
+/// The end of the comment or the literal starting at `i` , in which delimiters do not count.
+fn skip_non_code(content: &str, i: usize) -> Option<usize> {
+    let bytes = content.as_bytes();
+
+    let next = match bytes[i] {
+        b'/' if bytes.get(i + 1) == Some(&b'/') => {
+            content[i..].find('\n').map_or(bytes.len(), |n| i + n)
+        }
+        b'/' if bytes.get(i + 1) == Some(&b'*') => skip_block_comment(bytes, i),
+        b'"' => skip_string(bytes, i + 1),
+        b'r' if is_raw_string_start(bytes, i) => skip_raw_string(bytes, i + 1),
+        b'\'' => skip_char_or_lifetime(content, i),
+        _ => return None,
+    };
+
+    Some(next)
+}
+
+/// Whether the code after `}` continues the same expression or item.
+fn continues(rest: &str) -> bool {
+    let rest = rest.trim_start();
+
+    rest.starts_with(|c: char| ";.?,)]}=+-*/%&|^<>!".contains(c))
+        || ["else", "as"]
+            .iter()
+            .any(|kw| rest.starts_with(kw) && !rest[kw.len()..].starts_with(is_ident_char))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn skip_block_comment(bytes: &[u8], mut i: usize) -> usize {
+    let mut depth = 0;
+
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    i
+}
+
+/// `i` is just after the opening `"` .
+fn skip_string(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+/// `r"..."` or `r#"..."#` which is not a part of an identifier (`br` and `cr` are ok).
+fn is_raw_string_start(bytes: &[u8], i: usize) -> bool {
+    let prefix_ok = match i.checked_sub(1).map(|p| bytes[p]) {
+        Some(b'b' | b'c') => i < 2 || !(bytes[i - 2] as char).is_alphanumeric(),
+        Some(p) => !is_ident_char(p as char),
+        None => true,
+    };
+
+    let hashes = bytes[i + 1..].iter().take_while(|b| **b == b'#').count();
+
+    prefix_ok && bytes.get(i + 1 + hashes) == Some(&b'"')
+}
+
+/// `i` is just after `r` .
+fn skip_raw_string(bytes: &[u8], i: usize) -> usize {
+    let hashes = bytes[i..].iter().take_while(|b| **b == b'#').count();
+    let mut closing = vec![b'"'];
+    closing.extend(std::iter::repeat_n(b'#', hashes));
+
+    let body = i + hashes + 1;
+    bytes[body..]
+        .windows(closing.len())
+        .position(|w| w == closing.as_slice())
+        .map_or(bytes.len(), |p| body + p + closing.len())
+}
+
+fn skip_char_or_lifetime(content: &str, i: usize) -> usize {
+    let bytes = content.as_bytes();
+
+    match bytes.get(i + 1) {
+        // '\n' , '\'' , '\u{1F980}'
+        Some(b'\\') => {
+            // the escaped char may be `'`
+            let from = (i + 3).min(bytes.len());
+            let end = bytes[from..].iter().position(|b| *b == b'\'');
+            end.map_or(bytes.len(), |p| from + p + 1)
+        }
+        Some(_) => {
+            // a char literal is closed right after one char, otherwise it is a lifetime
+            let c_len = content[i + 1..].chars().next().map_or(1, char::len_utf8);
+
+            if bytes.get(i + 1 + c_len) == Some(&b'\'') {
+                i + 1 + c_len + 1
+            } else {
+                i + 1
+            }
+        }
+        None => i + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(content: &str) -> Vec<&str> {
+        split_pieces(content)
+            .into_iter()
+            .map(|range| &content[range])
+            .collect()
+    }
+
+    #[test]
+    fn splits_at_top_level_semicolons_and_braces() {
+        assert_eq!(
+            pieces("let a = 1; fn f() { g(); }\nstruct S;"),
+            ["let a = 1;", "fn f() { g(); }", "struct S;"]
+        );
+        assert_eq!(
+            pieces("if a { b } else { c }; x"),
+            ["if a { b } else { c };", "x"]
+        );
+    }
+
+    #[test]
+    fn escaped_quote_is_a_char() {
+        assert_eq!(skip_char_or_lifetime(r"'\'' x", 0), 4);
+        assert_eq!(skip_char_or_lifetime(r"'\n' x", 0), 4);
+        assert_eq!(skip_char_or_lifetime(r"'\u{1F980}' x", 0), 11);
+        assert_eq!(
+            pieces(r"let a = '\''; let b = ';';"),
+            [r"let a = '\'';", "let b = ';';"]
+        );
+    }
+
+    #[test]
+    fn lifetimes_and_chars() {
+        assert_eq!(skip_char_or_lifetime("'a: loop {}", 0), 1);
+        assert_eq!(skip_char_or_lifetime("'a' x", 0), 3);
+        assert_eq!(skip_char_or_lifetime("'あ' x", 0), 5);
+        assert_eq!(
+            pieces("fn f<'a>(x: &'a str) {} let c = '}';"),
+            ["fn f<'a>(x: &'a str) {}", "let c = '}';"]
+        );
+    }
+
+    #[test]
+    fn delimiters_in_comments_and_strings_are_ignored() {
+        assert_eq!(
+            pieces("let a = \"}\"; // }\nlet b = r#\"{\"#; /* { */ x;"),
+            ["let a = \"}\";", "// }\nlet b = r#\"{\"#;", "/* { */ x;"]
+        );
+    }
+
+    #[test]
+    fn unbalanced_delimiters() {
+        assert_eq!(
+            unbalanced_delimiter("f((1, 2]"),
+            Some(("mismatched closing delimiter `]` for `(`".to_string(), 7..8))
+        );
+        assert_eq!(
+            unbalanced_delimiter("f(1))"),
+            Some(("unexpected closing delimiter `)`".to_string(), 4..5))
+        );
+        assert_eq!(
+            unbalanced_delimiter("fn f() { if a {"),
+            Some(("unclosed delimiter `{`".to_string(), 14..15))
+        );
+        assert_eq!(unbalanced_delimiter("f(')', \"(\")"), None);
+    }
+
+    #[test]
+    fn failed_piece_tells_the_unbalanced_delimiter() {
+        let content = "let a = 1;\nfn f() { (1, 2]; }";
+        let recovered = recover(content, &WholeMatcher::DEFAULT_ORDER, Edition::E2021);
+
+        let [Piece::Parsed { range, .. }, Piece::Failed {
+            message,
+            error_range,
+            ..
+        }] = recovered.pieces.as_slice()
+        else {
+            panic!("{:?}", recovered.pieces);
+        };
+
+        assert_eq!(&content[range.clone()], "let a = 1;");
+        assert_eq!(message, "mismatched closing delimiter `]` for `(`");
+        assert_eq!(&content[error_range.clone()], "]");
+    }
+}
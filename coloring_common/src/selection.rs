@@ -0,0 +1,125 @@
+use crate::{
+    classify, stack_at, Classification, Edition, FragNode, FragmentAt, Fragments, WholeMatcher,
+};
+use std::ops::Range;
+
+/// What a part of a file is.
+#[derive(Debug)]
+pub struct SelectionClassification {
+    /// The selection without surrounding whitespaces.
+    pub selection: Range<usize>,
+    /// Fragments of the file which span exactly the selection.
+    pub exact: Vec<FragmentAt>,
+    /// Fragments of the file which contain the selection and more, innermost first.
+    pub enclosing: Vec<FragmentAt>,
+    /// Nodes of the file which the selection cuts in the middle, outermost ones only.
+    pub crossed: Vec<FragNode>,
+    /// The selected text classified on its own. Its ranges are relative to the selection.
+    pub standalone: Classification,
+    /// Why the whole file could not be parsed for the context.
+    pub context_error: Option<syn::Error>,
+}
+
+impl SelectionClassification {
+    /// Explains why the selection is not a fragment of the file, if so.
+    pub fn explanation(&self) -> Option<String> {
+        if !self.exact.is_empty() {
+            return None;
+        }
+
+        if let Some(error) = &self.context_error {
+            return Some(format!(
+                "the file could not be parsed for the context: {}",
+                error
+            ));
+        }
+
+        let mut res = "no fragment of the file spans exactly the selection".to_string();
+
+        for node in &self.crossed {
+            let frags = node
+                .frags
+                .iter()
+                .map(|f| f.to_string().to_ascii_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let Range { start, end } = node.range.byte.clone();
+
+            let side = if start < self.selection.start {
+                "starts inside"
+            } else {
+                "ends inside"
+            };
+
+            res.push_str(&format!(
+                "\n  the selection {} `{}` ( {} ) at {}..{}",
+                side, node.kind, frags, start, end
+            ));
+        }
+
+        Some(res)
+    }
+}
+
+/// Classifies `selection` of `content` , parsing the whole `content` as a file for the context.
+/// `matchers` are tried for the selected text on its own.
+///
+/// `selection` must be on char boundaries of `content` .
+pub fn classify_selection(
+    content: &str,
+    selection: Range<usize>,
+    matchers: &[WholeMatcher],
+    edition: Edition,
+) -> SelectionClassification {
+    let selected = &content[selection.clone()];
+    let start = selection.start + (selected.len() - selected.trim_start().len());
+    let end = start + selected.trim().len();
+    let selection = start..end;
+
+    let standalone = classify(&content[selection.clone()], matchers, edition);
+
+    let (tree, context_error) = match WholeMatcher::File.try_parse(content, edition) {
+        Ok(Fragments { tree, .. }) => (tree, None),
+        Err(e) => (Vec::new(), Some(e)),
+    };
+
+    let (exact, enclosing) = if selection.is_empty() {
+        (Vec::new(), Vec::new())
+    } else {
+        stack_at(&tree, selection.start)
+            .into_iter()
+            .filter(|f| f.range.byte.end >= selection.end)
+            .partition(|f| f.range.byte == selection)
+    };
+
+    let mut crossed = Vec::new();
+    collect_crossed(&tree, &selection, &mut crossed);
+
+    SelectionClassification {
+        selection,
+        exact,
+        enclosing,
+        crossed,
+        standalone,
+        context_error,
+    }
+}
+
+fn collect_crossed(nodes: &[FragNode], selection: &Range<usize>, res: &mut Vec<FragNode>) {
+    for node in nodes {
+        let Range { start, end } = node.range.byte.clone();
+
+        let overlaps = start < selection.end && selection.start < end;
+        let contains = start <= selection.start && selection.end <= end;
+        let contained = selection.start <= start && end <= selection.end;
+
+        if overlaps && !contains && !contained {
+            res.push(FragNode {
+                children: Vec::new(),
+                ..node.clone()
+            });
+        } else if overlaps && contains {
+            collect_crossed(&node.children, selection, res);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{FragNode, FragSpecs};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Metrics of the fragments found in one or more contents, to compare code styles.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    /// Total length of the contents.
+    pub bytes: usize,
+    pub frags: BTreeMap<FragSpecs, FragStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FragStats {
+    pub count: usize,
+    /// Bytes in at least one of the fragments.
+    pub coverage: usize,
+    /// Most fragments of the specifier nested in one another. 1 if none of them is nested.
+    pub max_depth: usize,
+    /// Number of the fragments by the name of the syn type, e.g. `ExprMethodCall` .
+    pub kinds: BTreeMap<&'static str, usize>,
+}
+
+/// Metrics of `tree` found in a content of `len` bytes.
+pub fn stats(len: usize, tree: &[FragNode]) -> Stats {
+    let mut counter = Counter {
+        len,
+        stats: Stats {
+            bytes: len,
+            ..Default::default()
+        },
+        covered: HashMap::new(),
+        depths: HashMap::new(),
+    };
+
+    for root in tree {
+        counter.visit(root);
+    }
+
+    let Counter {
+        mut stats, covered, ..
+    } = counter;
+
+    for (frag, covered) in covered {
+        if let Some(frag_stats) = stats.frags.get_mut(&frag) {
+            frag_stats.coverage = covered.iter().filter(|c| **c).count();
+        }
+    }
+
+    stats
+}
+
+impl Stats {
+    /// Adds up the metrics of another content. `max_depth` is the larger one.
+    pub fn merge(&mut self, other: Stats) {
+        self.bytes += other.bytes;
+
+        for (frag, other) in other.frags {
+            let frag_stats = self.frags.entry(frag).or_default();
+
+            frag_stats.count += other.count;
+            frag_stats.coverage += other.coverage;
+            frag_stats.max_depth = frag_stats.max_depth.max(other.max_depth);
+            for (kind, count) in other.kinds {
+                *frag_stats.kinds.entry(kind).or_default() += count;
+            }
+        }
+    }
+}
+
+struct Counter {
+    len: usize,
+    stats: Stats,
+    /// Whether each byte is in a fragment of the specifier.
+    covered: HashMap<FragSpecs, Vec<bool>>,
+    /// Number of the ancestors of the specifier, including the current node.
+    depths: HashMap<FragSpecs, usize>,
+}
+
+impl Counter {
+    fn visit(&mut self, node: &FragNode) {
+        let byte = &node.range.byte;
+        let covered_range = byte.start.min(self.len)..byte.end.min(self.len);
+
+        // a node without tokens, e.g. at the call site, is not a fragment found in the content
+        let frags: &[FragSpecs] = if byte.is_empty() { &[] } else { &node.frags };
+
+        for frag in frags {
+            let depth = self.depths.entry(*frag).or_default();
+            *depth += 1;
+
+            let frag_stats = self.stats.frags.entry(*frag).or_default();
+            frag_stats.count += 1;
+            frag_stats.max_depth = frag_stats.max_depth.max(*depth);
+            *frag_stats.kinds.entry(node.kind).or_default() += 1;
+
+            let covered = self
+                .covered
+                .entry(*frag)
+                .or_insert_with(|| vec![false; self.len]);
+            covered[covered_range.clone()].fill(true);
+        }
+
+        for child in &node.children {
+            self.visit(child);
+        }
+
+        for frag in frags {
+            if let Some(depth) = self.depths.get_mut(frag) {
+                *depth -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Edition, WholeMatcher};
+
+    fn stats_of(matcher: WholeMatcher, content: &str) -> Stats {
+        let tree = matcher.try_parse(content, Edition::E2021).unwrap().tree;
+
+        stats(content.len(), &tree)
+    }
+
+    #[test]
+    fn nested_expressions() {
+        let stats = stats_of(WholeMatcher::Expr, "f(g(x)) + 1");
+        assert_eq!(stats.bytes, 11);
+
+        let expr = &stats.frags[&FragSpecs::Expr];
+        assert_eq!(expr.count, 7);
+        assert_eq!(expr.coverage, 11);
+        // `f(g(x)) + 1` , `f(g(x))` , `g(x)` and `x`
+        assert_eq!(expr.max_depth, 4);
+        assert_eq!(
+            expr.kinds,
+            BTreeMap::from([
+                ("ExprBinary", 1),
+                ("ExprCall", 2),
+                ("ExprLit", 1),
+                ("ExprPath", 3),
+            ])
+        );
+
+        let ident = &stats.frags[&FragSpecs::Ident];
+        assert_eq!((ident.count, ident.coverage, ident.max_depth), (3, 3, 1));
+
+        assert!(!stats.frags.contains_key(&FragSpecs::Ty));
+    }
+
+    #[test]
+    fn overlapping_fragments_are_covered_once() {
+        let stats = stats_of(WholeMatcher::Type, "Vec<Vec<u8>>");
+        let ty = &stats.frags[&FragSpecs::Ty];
+
+        assert_eq!((ty.count, ty.coverage, ty.max_depth), (3, 12, 3));
+    }
+
+    #[test]
+    fn merged() {
+        let mut stats = stats_of(WholeMatcher::Expr, "f(g(x)) + 1");
+        stats.merge(stats_of(WholeMatcher::Expr, "y"));
+        stats.merge(stats_of(WholeMatcher::Type, "u8"));
+
+        assert_eq!(stats.bytes, 14);
+
+        let expr = &stats.frags[&FragSpecs::Expr];
+        assert_eq!((expr.count, expr.coverage, expr.max_depth), (8, 12, 4));
+        assert_eq!(expr.kinds["ExprPath"], 4);
+
+        let ty = &stats.frags[&FragSpecs::Ty];
+        assert_eq!((ty.count, ty.coverage, ty.max_depth), (1, 2, 1));
+    }
+}
@@ -0,0 +1,224 @@
+use crate::position::byte_ranges;
+use crate::{Colored, Edition, FragNode, FragRangeMap, FragSpecs};
+use clap::ValueEnum;
+use coloring_macro::repeat_for_types;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::ops::Range;
+use strum::Display;
+use syn::parse::Parser;
+use syn::spanned::Spanned;
+
+pub type RangeMap = HashMap<FragSpecs, Vec<Range<usize>>>;
+
+/// What [`Colored`] found, in the flat form and the tree form.
+#[derive(Debug)]
+pub struct Fragments {
+    pub ranges: FragRangeMap,
+    pub tree: Vec<FragNode>,
+}
+
+impl Fragments {
+    /// Byte ranges only.
+    pub fn byte_ranges(&self) -> RangeMap {
+        byte_ranges(&self.ranges)
+    }
+}
+
+impl<T: Debug> From<Colored<T>> for Fragments {
+    fn from(colored: Colored<T>) -> Self {
+        Self {
+            ranges: colored.ranges,
+            tree: colored.tree,
+        }
+    }
+}
+
+/// Syntax which the whole given code is tried to be parsed as.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, ValueEnum, Serialize, Deserialize, Display)]
+#[serde(rename_all = "lowercase")]
+pub enum WholeMatcher {
+    File,
+    Item,
+    Block,
+    Stmt,
+    Expr,
+    #[serde(rename = "ty")]
+    #[value(name = "ty")]
+    Type,
+    Path,
+    #[serde(rename = "vis")]
+    #[value(name = "vis")]
+    Visibility,
+    Ident,
+    Lifetime,
+    #[serde(rename = "literal")]
+    #[value(name = "literal")]
+    Lit,
+    Meta,
+    Pat,
+    #[serde(rename = "pat_param")]
+    #[value(name = "pat_param")]
+    PatParam,
+}
+
+/// Name of `matcher` in `--matchers` and JSON, e.g. `ty` .
+pub fn matcher_name(matcher: WholeMatcher) -> String {
+    matcher
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_else(|| matcher.to_string())
+}
+
+impl WholeMatcher {
+    pub const DEFAULT_ORDER: [WholeMatcher; 14] = [
+        WholeMatcher::File,
+        WholeMatcher::Item,
+        WholeMatcher::Block,
+        WholeMatcher::Stmt,
+        WholeMatcher::Expr,
+        WholeMatcher::Type,
+        WholeMatcher::Path,
+        WholeMatcher::Visibility,
+        WholeMatcher::Ident,
+        WholeMatcher::Lifetime,
+        WholeMatcher::Lit,
+        WholeMatcher::Meta,
+        WholeMatcher::Pat,
+        WholeMatcher::PatParam,
+    ];
+
+    pub fn try_parse(self, content: &str, edition: Edition) -> syn::Result<Fragments> {
+        let tokens = edition.tokenize(content)?;
+
+        repeat_for_types!(for M in [
+            File,
+            Item,
+            Block,
+            Stmt,
+            Type,
+            Path,
+            Visibility,
+            Ident,
+            Lifetime,
+            Lit,
+            Meta,
+        ] {
+            match self {
+                #(
+                    WholeMatcher::M => {
+                        let parsed = syn::parse2::<syn::M>(tokens)?;
+
+                        let mut colored = Colored::<syn::M>::new(parsed)
+                            .set_edition(edition)
+                            .set_source(content);
+                        colored.visit();
+
+                        Ok(colored.into())
+                    },
+                )*
+                WholeMatcher::Expr => {
+                    let parsed = syn::parse2::<syn::Expr>(tokens)?;
+
+                    if matches!(parsed, syn::Expr::Const(_) | syn::Expr::Infer(_))
+                        && !edition.has_expr_2024()
+                    {
+                        return Err(syn::Error::new(
+                            parsed.span(),
+                            "`const {}` and `_` are `expr` only since edition 2024",
+                        ));
+                    }
+
+                    let mut colored = Colored::<syn::Expr>::new(parsed)
+                        .set_edition(edition)
+                        .set_source(content);
+                    colored.visit();
+
+                    Ok(colored.into())
+                }
+                WholeMatcher::Pat => {
+                    let parsed = if edition.has_or_patterns() {
+                        syn::Pat::parse_multi_with_leading_vert.parse2(tokens)?
+                    } else {
+                        syn::Pat::parse_single.parse2(tokens)?
+                    };
+
+                    let mut colored = Colored::<syn::Pat>::new(parsed)
+                        .set_edition(edition)
+                        .set_source(content);
+                    colored.visit();
+
+                    Ok(colored.into())
+                }
+                WholeMatcher::PatParam => {
+                    let parsed = syn::Pat::parse_single.parse2(tokens)?;
+
+                    let mut colored = Colored::<syn::Pat>::new(parsed)
+                        .set_edition(edition)
+                        .set_source(content);
+                    colored.visit();
+
+                    Ok(colored.into())
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct TopMatch {
+    pub matcher: WholeMatcher,
+    pub ranges: FragRangeMap,
+    pub tree: Vec<FragNode>,
+}
+
+impl TopMatch {
+    /// Byte ranges only.
+    pub fn byte_ranges(&self) -> RangeMap {
+        byte_ranges(&self.ranges)
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchError {
+    pub matcher: WholeMatcher,
+    pub error: syn::Error,
+}
+
+#[derive(Debug)]
+pub struct Classification {
+    /// The first matcher (in the given order) which succeeded.
+    pub top_match: Option<TopMatch>,
+    /// Every matcher which succeeded, including the top one.
+    pub hit_matchers: Vec<WholeMatcher>,
+    pub errors: Vec<MatchError>,
+}
+
+pub fn classify(content: &str, matchers: &[WholeMatcher], edition: Edition) -> Classification {
+    let mut top_match = None;
+    let mut hit_matchers = Vec::new();
+    let mut errors = Vec::new();
+
+    for &matcher in matchers {
+        match matcher.try_parse(content, edition) {
+            Ok(Fragments { ranges, tree }) => {
+                if top_match.is_none() {
+                    top_match = Some(TopMatch {
+                        matcher,
+                        ranges,
+                        tree,
+                    });
+                }
+                hit_matchers.push(matcher);
+            }
+            Err(error) => errors.push(MatchError { matcher, error }),
+        }
+    }
+
+    Classification {
+        top_match,
+        hit_matchers,
+        errors,
+    }
+}
@@ -0,0 +1,526 @@
+use crate::fragment_tree::NodeKind;
+use crate::{matcher_name, Edition, MatchError, WholeMatcher};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use serde::Serialize;
+use std::ops::Range;
+
+/// Why a matcher did not accept the whole content, in words.
+#[derive(Debug, Clone, Serialize)]
+pub struct WhyNot {
+    pub matcher: WholeMatcher,
+    pub explanation: String,
+}
+
+/// Explains every failed matcher of `errors` , which are the ones for `content` .
+pub fn why_not(content: &str, errors: &[MatchError], edition: Edition) -> Vec<WhyNot> {
+    let tokens = edition.tokenize(content).ok();
+
+    // e.g. `ExprBinary` , when the content is an expression
+    let expr_kind = tokens
+        .clone()
+        .and_then(|tokens| syn::parse2::<syn::Expr>(tokens).ok())
+        .map(|expr| expr.node_kind());
+
+    errors
+        .iter()
+        .map(|error| {
+            let context = Context::new(content, error, tokens.clone(), expr_kind);
+
+            let explanation = RULES
+                .iter()
+                .filter(|rule| rule.matchers.is_empty() || rule.matchers.contains(&error.matcher))
+                .find(|rule| (rule.applies)(&context))
+                .map_or_else(|| fallback(&context), |rule| (rule.explain)(&context));
+
+            WhyNot {
+                matcher: error.matcher,
+                explanation,
+            }
+        })
+        .collect()
+}
+
+/// What the rules look at.
+struct Context<'a> {
+    matcher: WholeMatcher,
+    message: String,
+    /// `None` if the content cannot even be lexed.
+    tokens: Option<Vec<TokenTree>>,
+    /// Text of the token the error points at. Empty at the end of input.
+    error_text: &'a str,
+    /// The error points at the first token.
+    at_start: bool,
+    at_end: bool,
+    expr_kind: Option<&'static str>,
+}
+
+impl<'a> Context<'a> {
+    fn new(
+        content: &'a str,
+        MatchError { matcher, error }: &MatchError,
+        tokens: Option<TokenStream>,
+        expr_kind: Option<&'static str>,
+    ) -> Self {
+        let message = error.to_string();
+        let Range { start, end } = error.span().byte_range();
+        let at_end = message.starts_with("unexpected end of input");
+
+        let tokens = tokens.map(|tokens| tokens.into_iter().collect::<Vec<_>>());
+        let first_start = tokens
+            .as_ref()
+            .and_then(|tokens| tokens.first())
+            .map(|tt| tt.span().byte_range().start);
+
+        Self {
+            matcher: *matcher,
+            message,
+            tokens,
+            error_text: if at_end {
+                ""
+            } else {
+                content.get(start..end).unwrap_or_default()
+            },
+            at_start: !at_end && Some(start) == first_start,
+            at_end,
+            expr_kind,
+        }
+    }
+
+    fn first(&self) -> Option<&TokenTree> {
+        self.tokens.as_ref()?.first()
+    }
+
+    fn last(&self) -> Option<&TokenTree> {
+        self.tokens.as_ref()?.last()
+    }
+
+    fn token_count(&self) -> usize {
+        self.tokens.as_ref().map_or(0, Vec::len)
+    }
+
+    fn first_is_literal(&self) -> bool {
+        matches!(self.first(), Some(TokenTree::Literal(_)))
+    }
+
+    fn first_is_lifetime(&self) -> bool {
+        matches!(
+            self.tokens.as_deref(),
+            Some([TokenTree::Punct(p), TokenTree::Ident(_), ..]) if p.as_char() == '\''
+        )
+    }
+
+    fn first_keyword(&self) -> Option<String> {
+        match self.first()? {
+            TokenTree::Ident(ident) => {
+                let name = ident.to_string();
+                syn::parse_str::<syn::Ident>(&name).is_err().then_some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn first_punct(&self) -> Option<char> {
+        match self.first()? {
+            TokenTree::Punct(p) => Some(p.as_char()),
+            _ => None,
+        }
+    }
+
+    fn first_is_brace(&self) -> bool {
+        matches!(self.first(), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace)
+    }
+
+    fn ends_with_semicolon(&self) -> bool {
+        matches!(self.last(), Some(TokenTree::Punct(p)) if p.as_char() == ';')
+    }
+
+    fn expects_semicolon(&self) -> bool {
+        self.message.contains("semicolon") || self.message.contains("`;`")
+    }
+
+    /// The matcher as it is written in `macro_rules!` , e.g. `ty` .
+    fn frag(&self) -> String {
+        format!("`{}`", matcher_name(self.matcher))
+    }
+}
+
+/// Keywords which start a statement or an item but never an expression.
+const STATEMENT_KEYWORDS: [&str; 14] = [
+    "let", "fn", "struct", "enum", "union", "trait", "impl", "mod", "use", "static", "type",
+    "extern", "pub", "macro",
+];
+
+struct Rule {
+    /// Matchers the rule is for. Empty for all.
+    matchers: &'static [WholeMatcher],
+    applies: fn(&Context) -> bool,
+    explain: fn(&Context) -> String,
+}
+
+/// Tried in order. The first applicable one explains the failure.
+const RULES: &[Rule] = &[
+    Rule {
+        matchers: &[],
+        applies: |c| c.tokens.is_none(),
+        explain: |_| {
+            "the code cannot be split into tokens, e.g. because of unbalanced delimiters \
+             or an unterminated literal, so no fragment can match it"
+                .to_string()
+        },
+    },
+    Rule {
+        matchers: &[],
+        applies: |c| c.message.contains("edition"),
+        explain: |c| c.message.clone(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Stmt],
+        applies: |c| c.expects_semicolon() && c.error_text.is_empty(),
+        explain: missing_semicolon,
+    },
+    Rule {
+        matchers: &[
+            WholeMatcher::Expr,
+            WholeMatcher::Type,
+            WholeMatcher::Path,
+            WholeMatcher::Ident,
+            WholeMatcher::Lit,
+            WholeMatcher::Meta,
+            WholeMatcher::Pat,
+            WholeMatcher::PatParam,
+        ],
+        applies: |c| c.ends_with_semicolon() && c.error_text == ";",
+        explain: |c| {
+            format!(
+                "{} never includes a trailing `;` . Without it, the rest might match",
+                c.frag()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Expr, WholeMatcher::Stmt],
+        applies: |c| c.message.contains("comparison operators cannot be chained"),
+        explain: |c| {
+            format!(
+                "{} reads `<` and `>` as comparisons. \
+                 Generic arguments in an expression need a turbofish like `Vec::<u8>`",
+                c.frag()
+            )
+        },
+    },
+    Rule {
+        matchers: &[
+            WholeMatcher::Expr,
+            WholeMatcher::Type,
+            WholeMatcher::Pat,
+            WholeMatcher::PatParam,
+        ],
+        applies: |c| {
+            c.at_start
+                && c.first_keyword()
+                    .is_some_and(|kw| STATEMENT_KEYWORDS.contains(&kw.as_str()))
+        },
+        explain: |c| {
+            format!(
+                "{} cannot start with `{}` , which starts a statement or an item",
+                c.frag(),
+                c.first_keyword().unwrap_or_default()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Expr, WholeMatcher::Stmt],
+        applies: |c| c.at_start && matches!(c.first_keyword().as_deref(), Some("mut" | "ref")),
+        explain: |c| {
+            format!(
+                "{} cannot start with `{}` , which is a binding mode allowed only in patterns",
+                c.frag(),
+                c.first_keyword().unwrap_or_default()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Expr, WholeMatcher::Stmt],
+        applies: |c| c.first_is_lifetime() && c.message == "expected `:`",
+        explain: |c| {
+            format!(
+                "{} reads a leading lifetime as a label like `'a: loop {{}}`",
+                c.frag()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::File, WholeMatcher::Item, WholeMatcher::Stmt],
+        applies: |c| c.at_end && c.first_keyword().as_deref() == Some("pub"),
+        explain: |c| format!("{} needs an item after the visibility", c.frag()),
+    },
+    Rule {
+        matchers: &[WholeMatcher::File, WholeMatcher::Item, WholeMatcher::Stmt],
+        applies: |c| c.at_end && c.first_punct() == Some('#'),
+        explain: |c| {
+            format!(
+                "{} needs an item or a statement after the attributes they are attached to",
+                c.frag()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::File, WholeMatcher::Item],
+        applies: |c| c.message == "expected `!`",
+        explain: |c| {
+            format!(
+                "{} starting with a path must be a macro invocation like `name!(...)`",
+                c.frag()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::File, WholeMatcher::Item],
+        applies: |c| c.at_start,
+        explain: |c| {
+            format!(
+                "{} must start with an item keyword such as `fn` or `struct` , \
+                 attributes or a visibility",
+                c.frag()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Type],
+        applies: |c| c.at_start && c.first_is_literal(),
+        explain: |_| "`ty` cannot start with a literal".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Type],
+        applies: |c| c.first_is_lifetime(),
+        explain: |_| {
+            "`ty` cannot be a lifetime alone. \
+             A lifetime appears in a type only as a bound or a reference like `&'a T`"
+                .to_string()
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Block],
+        applies: |c| !c.first_is_brace(),
+        explain: |_| "`block` must be wrapped in `{ }`".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Block],
+        applies: |c| c.token_count() > 1,
+        explain: |_| "`block` is a single `{ ... }` with nothing after it".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Visibility],
+        applies: |c| c.at_start,
+        explain: |_| "`vis` must start with `pub` , or be empty".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Lit],
+        applies: |c| c.first_is_literal() || c.first_punct() == Some('-'),
+        explain: |_| {
+            "`literal` is a single literal token such as `1` or `\"s\"` , optionally negated"
+                .to_string()
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Lit],
+        applies: |_| true,
+        explain: |_| "`literal` must start with a literal such as `1` or `\"s\"`".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Lifetime],
+        applies: |c| c.first_is_lifetime(),
+        explain: |_| "`lifetime` is a single lifetime with nothing after it".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Lifetime],
+        applies: |_| true,
+        explain: |_| "`lifetime` must be a lifetime such as `'a`".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Ident, WholeMatcher::Path, WholeMatcher::Meta],
+        applies: |c| c.at_start && c.first_keyword().is_some(),
+        explain: |c| {
+            format!(
+                "{} is checked with syn, which rejects the keyword `{}` here",
+                c.frag(),
+                c.first_keyword().unwrap_or_default()
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Ident],
+        applies: |c| !c.at_start,
+        explain: |_| "`ident` is a single identifier with nothing after it".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Ident, WholeMatcher::Path],
+        applies: |c| c.at_start,
+        explain: |c| format!("{} must start with an identifier", c.frag()),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Path],
+        applies: |c| !c.at_end,
+        explain: |c| {
+            format!(
+                "`path` is `::` separated segments only, which cannot continue with `{}`",
+                c.error_text
+            )
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Meta],
+        applies: |c| c.first_punct() == Some('#'),
+        explain: |_| {
+            "`meta` is the inside of an attribute such as `derive(Debug)` , without `#[ ]`"
+                .to_string()
+        },
+    },
+    Rule {
+        matchers: &[WholeMatcher::Meta],
+        applies: |c| c.at_start,
+        explain: |_| "`meta` must start with a path such as `derive` or `doc`".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::PatParam],
+        applies: |c| c.error_text == "|",
+        explain: |_| "`pat_param` does not allow a top-level `|` . Use `pat` instead".to_string(),
+    },
+    Rule {
+        matchers: &[WholeMatcher::Pat, WholeMatcher::PatParam],
+        applies: |c| {
+            !c.at_start
+                && !c.error_text.is_empty()
+                && c.error_text.chars().all(|ch| ch.is_ascii_punctuation())
+        },
+        explain: |c| {
+            format!(
+                "{} cannot contain `{}` , which is an operator of expressions",
+                c.frag(),
+                c.error_text
+            )
+        },
+    },
+];
+
+fn missing_semicolon(c: &Context) -> String {
+    match c.expr_kind {
+        Some("ExprLet") => "`stmt` requires a trailing `;` after a `let` binding".to_string(),
+        Some(kind) => format!(
+            "`stmt` requires a trailing `;` for this expression kind ( `{}` ). \
+             Only block-like expressions such as `if` , `match` and `{{ }}` \
+             can be a statement without it",
+            kind
+        ),
+        _ => "`stmt` requires a trailing `;` here".to_string(),
+    }
+}
+
+fn fallback(c: &Context) -> String {
+    if c.at_end {
+        format!(
+            "{} needs more tokens after the content: {}",
+            c.frag(),
+            c.message
+        )
+    } else if c.at_start {
+        format!(
+            "{} cannot start with `{}`: {}",
+            c.frag(),
+            c.error_text,
+            c.message
+        )
+    } else {
+        format!(
+            "{} stops before `{}`: {}",
+            c.frag(),
+            c.error_text,
+            c.message
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classify;
+
+    fn explain(matcher: WholeMatcher, content: &str) -> String {
+        let errors = classify(content, &[matcher], Edition::E2021).errors;
+
+        match why_not(content, &errors, Edition::E2021).as_slice() {
+            [WhyNot { explanation, .. }] => explanation.clone(),
+            why_not => panic!("{} explanations for {:?}", why_not.len(), content),
+        }
+    }
+
+    #[test]
+    fn every_rule_applies() {
+        use WholeMatcher::*;
+
+        let cases = [
+            (Expr, "f(x", "cannot be split into tokens"),
+            (Stmt, "let x = 1", "trailing `;` after a `let` binding"),
+            (Stmt, "x + 1", "this expression kind ( `ExprBinary` )"),
+            (Expr, "x + 1;", "`expr` never includes a trailing `;`"),
+            (Expr, "Vec<u8>::new()", "turbofish"),
+            (Expr, "fn f() {}", "`expr` cannot start with `fn`"),
+            (Expr, "mut x", "binding mode"),
+            (Expr, "'a", "label"),
+            (Item, "pub", "an item after the visibility"),
+            (Item, "#[derive(Debug)]", "after the attributes"),
+            (Item, "foo", "macro invocation"),
+            (Item, "1 + 2", "must start with an item keyword"),
+            (Type, "1", "`ty` cannot start with a literal"),
+            (Type, "'a", "`ty` cannot be a lifetime alone"),
+            (Block, "x", "wrapped in `{ }`"),
+            (Block, "{} x", "nothing after it"),
+            (Visibility, "x", "`vis` must start with `pub`"),
+            (Lit, "1 + 2", "a single literal token"),
+            (Lit, "x", "`literal` must start with a literal"),
+            (Lifetime, "'a b", "a single lifetime"),
+            (Lifetime, "x", "must be a lifetime"),
+            (Ident, "self::x", "rejects the keyword `self`"),
+            (Ident, "x y", "a single identifier"),
+            (Ident, "1", "`ident` must start with an identifier"),
+            (Path, "x + 1", "cannot continue with `+`"),
+            (Meta, "#[x]", "without `#[ ]`"),
+            (Meta, "1", "`meta` must start with a path"),
+            (PatParam, "A | B", "Use `pat` instead"),
+            (Pat, "a + b", "`pat` cannot contain `+`"),
+        ];
+
+        for (matcher, content, expected) in cases {
+            let explanation = explain(matcher, content);
+
+            assert!(
+                explanation.contains(expected),
+                "{:?} {:?}: {}",
+                matcher,
+                content,
+                explanation
+            );
+        }
+    }
+
+    #[test]
+    fn fallback_tells_where_it_stops() {
+        assert_eq!(
+            explain(WholeMatcher::Expr, "1 +"),
+            "`expr` needs more tokens after the content: \
+             unexpected end of input, expected an expression"
+        );
+    }
+
+    #[test]
+    fn accepting_matchers_are_not_explained() {
+        let content = "x";
+        let classification = classify(content, &WholeMatcher::DEFAULT_ORDER, Edition::E2021);
+        let why_not = why_not(content, &classification.errors, Edition::E2021);
+
+        assert!(why_not
+            .iter()
+            .all(|w| !classification.hit_matchers.contains(&w.matcher)));
+        assert_eq!(why_not.len(), classification.errors.len());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+/// LSP position. `character` is counted in UTF-16 code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// Converts LSP positions from/into byte offsets.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    /// Byte offset of each line.
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self { text, line_starts }
+    }
+
+    /// A position beyond the end of its line is the end of the line, as the spec says.
+    pub fn offset(&self, Position { line, character }: Position) -> Option<usize> {
+        let start = *self.line_starts.get(line)?;
+        let line_text = self.line(line);
+
+        let mut utf16 = 0;
+        for (i, c) in line_text.char_indices() {
+            if utf16 >= character {
+                return Some(start + i);
+            }
+            utf16 += c.len_utf16();
+        }
+
+        Some(start + line_text.len())
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+
+        Position { line, character }
+    }
+
+    pub fn range(&self, range: std::ops::Range<usize>) -> Range {
+        Range {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        }
+    }
+
+    pub fn byte_range(&self, Range { start, end }: Range) -> Option<std::ops::Range<usize>> {
+        Some(self.offset(start)?..self.offset(end)?)
+    }
+
+    /// Without the line break.
+    fn line(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line];
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text.len(), |next| next - 1);
+
+        self.text[start..end].trim_end_matches('\r')
+    }
+}
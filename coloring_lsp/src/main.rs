@@ -0,0 +1,45 @@
+mod line_index;
+mod rpc;
+mod server;
+
+use anyhow::Result;
+use clap::Parser;
+use coloring_common::Edition;
+use server::{Flow, Server};
+use std::io::{stdin, stdout};
+
+/// Language server telling which fragment specifiers of `macro_rules!` the code satisfies.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Default edition. `initializationOptions.edition` overrides it
+    #[arg(long, value_enum, default_value_t)]
+    edition: Edition,
+
+    /// Communicate over stdio, which is the only way supported
+    #[arg(long)]
+    stdio: bool,
+}
+
+fn main() -> Result<()> {
+    let Cli { edition, .. } = Cli::parse();
+
+    let mut server = Server::new(edition);
+
+    let mut input = stdin().lock();
+    let mut output = stdout().lock();
+
+    while let Some(message) = rpc::read_message(&mut input)? {
+        let (responses, flow) = server.handle(&message);
+
+        for response in &responses {
+            rpc::write_message(&mut output, response)?;
+        }
+
+        if let Flow::Exit(code) = flow {
+            std::process::exit(code);
+        }
+    }
+
+    Ok(())
+}
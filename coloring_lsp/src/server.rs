@@ -0,0 +1,678 @@
+use crate::line_index::{LineIndex, Range};
+use crate::rpc::{self, Incoming, ResponseError};
+use clap::ValueEnum;
+use coloring_common::mbe::match_whole_str;
+use coloring_common::{
+    classify, matcher_name, outline, paint, stack_at, Edition, FragSpecs, TopMatch, WholeMatcher,
+};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+
+pub const SHOW_FRAGMENT_TREE: &str = "coloring.showFragmentTree";
+
+pub struct Server {
+    edition: Edition,
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    /// Messages to be sent after the current one is handled.
+    outgoing: Vec<Value>,
+}
+
+/// An open document.
+struct Document {
+    text: String,
+    /// Classification of `text` , made at the first request for this version of the document.
+    top_match: OnceCell<Option<TopMatch>>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        Self {
+            text,
+            top_match: OnceCell::new(),
+        }
+    }
+}
+
+/// What to do after handling a message.
+pub enum Flow {
+    Continue,
+    Exit(i32),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentItem {
+    uri: String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidOpenParams {
+    text_document: TextDocumentItem,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentChange {
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidChangeParams {
+    text_document: TextDocumentIdentifier,
+    content_changes: Vec<ContentChange>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidCloseParams {
+    text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PositionParams {
+    text_document: TextDocumentIdentifier,
+    position: crate::line_index::Position,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentParams {
+    text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeActionParams {
+    text_document: TextDocumentIdentifier,
+    range: Range,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExecuteCommandParams {
+    command: String,
+    #[serde(default)]
+    arguments: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitializationOptions {
+    edition: Option<Edition>,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, ResponseError> {
+    serde_json::from_value(params).map_err(|e| ResponseError::new(rpc::INVALID_PARAMS, e))
+}
+
+/// Token types of semantic tokens, which are the names of fragment specifiers.
+fn token_types() -> Vec<String> {
+    FragSpecs::value_variants()
+        .iter()
+        .map(|frag| frag.to_string().to_ascii_lowercase())
+        .collect()
+}
+
+impl Server {
+    pub fn new(edition: Edition) -> Self {
+        Self {
+            edition,
+            documents: HashMap::new(),
+            shutdown: false,
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// Handles one message and returns the messages to be sent back.
+    pub fn handle(&mut self, message: &str) -> (Vec<Value>, Flow) {
+        let mut res = Vec::new();
+
+        let Incoming { id, method, params } = match serde_json::from_str::<Incoming>(message) {
+            Ok(incoming) => incoming,
+            Err(e) => {
+                let error = ResponseError::new(rpc::PARSE_ERROR, e);
+                res.push(rpc::response(Value::Null, Err(error)));
+
+                return (res, Flow::Continue);
+            }
+        };
+
+        let flow = match (id, method) {
+            (Some(id), Some(method)) => {
+                let result = self.request(&method, params);
+                res.push(rpc::response(id, result));
+
+                Flow::Continue
+            }
+            (None, Some(method)) => self.notification(&method, params),
+            // a response to the server, which never sends requests
+            (Some(_), None) => Flow::Continue,
+            (None, None) => {
+                let error = ResponseError::new(rpc::INVALID_REQUEST, "method is missing");
+                res.push(rpc::response(Value::Null, Err(error)));
+
+                Flow::Continue
+            }
+        };
+
+        res.append(&mut self.outgoing);
+
+        // every tokenization adds to the source map of spans, which is never freed otherwise.
+        // Nothing kept across messages has a span.
+        proc_macro2::extra::invalidate_current_thread_spans();
+
+        (res, flow)
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        if self.shutdown {
+            return Err(ResponseError::new(
+                rpc::INVALID_REQUEST,
+                "the server is shutting down",
+            ));
+        }
+
+        match method {
+            "initialize" => Ok(self.initialize(params)),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.hover(self::params(params)?),
+            "textDocument/semanticTokens/full" => self.semantic_tokens(self::params(params)?),
+            "textDocument/codeAction" => self.code_action(self::params(params)?),
+            "workspace/executeCommand" => self.execute_command(self::params(params)?),
+            _ => Err(ResponseError::new(
+                rpc::METHOD_NOT_FOUND,
+                format!("unknown method: {}", method),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> Flow {
+        match method {
+            "exit" => return Flow::Exit(if self.shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                if let Ok(DidOpenParams { text_document }) = self::params(params) {
+                    self.documents
+                        .insert(text_document.uri, Document::new(text_document.text));
+                }
+            }
+            "textDocument/didChange" => {
+                if let Ok(DidChangeParams {
+                    text_document,
+                    content_changes,
+                }) = self::params(params)
+                {
+                    // full sync, so the last change has the whole text
+                    if let Some(change) = content_changes.into_iter().last() {
+                        self.documents
+                            .insert(text_document.uri, Document::new(change.text));
+                    }
+                }
+            }
+            "textDocument/didClose" => {
+                if let Ok(DidCloseParams { text_document }) = self::params(params) {
+                    self.documents.remove(&text_document.uri);
+                }
+            }
+            _ => {}
+        }
+
+        Flow::Continue
+    }
+
+    fn initialize(&mut self, params: Value) -> Value {
+        let options = params
+            .get("initializationOptions")
+            .cloned()
+            .and_then(|options| serde_json::from_value::<InitializationOptions>(options).ok());
+
+        if let Some(InitializationOptions {
+            edition: Some(edition),
+        }) = options
+        {
+            self.edition = edition;
+        }
+
+        json!({
+            "capabilities": {
+                // full
+                "textDocumentSync": 1,
+                "hoverProvider": true,
+                "semanticTokensProvider": {
+                    "legend": {
+                        "tokenTypes": token_types(),
+                        "tokenModifiers": [],
+                    },
+                    "full": true,
+                },
+                "codeActionProvider": true,
+                "executeCommandProvider": {
+                    "commands": [SHOW_FRAGMENT_TREE],
+                },
+            },
+            "serverInfo": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })
+    }
+
+    fn document(&self, uri: &str) -> Result<&Document, ResponseError> {
+        self.documents.get(uri).ok_or_else(|| {
+            ResponseError::new(rpc::INVALID_PARAMS, format!("unknown document: {}", uri))
+        })
+    }
+
+    fn top_match(&self, text: &str) -> Option<TopMatch> {
+        classify(text, &WholeMatcher::DEFAULT_ORDER, self.edition).top_match
+    }
+
+    /// Classifies the document once per version, since it may be large.
+    fn document_top_match<'a>(&self, document: &'a Document) -> Option<&'a TopMatch> {
+        document
+            .top_match
+            .get_or_init(|| self.top_match(&document.text))
+            .as_ref()
+    }
+
+    /// "`f(x)` is `expr`, `stmt`; not `ty`, ..." for the innermost fragment under the cursor.
+    fn hover(
+        &self,
+        PositionParams {
+            text_document,
+            position,
+        }: PositionParams,
+    ) -> Result<Value, ResponseError> {
+        let document = self.document(&text_document.uri)?;
+        let text = document.text.as_str();
+        let index = LineIndex::new(text);
+
+        let Some(offset) = index.offset(position) else {
+            return Ok(Value::Null);
+        };
+        let Some(TopMatch { tree, .. }) = self.document_top_match(document) else {
+            return Ok(Value::Null);
+        };
+        let stack = stack_at(tree, offset);
+        let Some(innermost) = stack.first() else {
+            return Ok(Value::Null);
+        };
+
+        let byte_range = innermost.range.byte.clone();
+        let fragment = &text[byte_range.clone()];
+
+        // asked to the macro-by-example emulation since rustc may disagree with syn's tree
+        let (is, is_not): (Vec<_>, Vec<_>) = FragSpecs::value_variants()
+            .iter()
+            .partition(|frag| match_whole_str(**frag, fragment, self.edition).is_ok());
+
+        let list = |frags: Vec<&FragSpecs>| {
+            frags
+                .iter()
+                .map(|frag| format!("`{}`", frag.to_string().to_ascii_lowercase()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut value = format!("**{}**\n\n", innermost.kind);
+        if is.is_empty() {
+            value.push_str("this is not any fragment");
+        } else {
+            value.push_str(&format!("this is {}", list(is)));
+        }
+        if !is_not.is_empty() {
+            value.push_str(&format!("; not {}", list(is_not)));
+        }
+
+        Ok(json!({
+            "contents": {
+                "kind": "markdown",
+                "value": value,
+            },
+            "range": index.range(byte_range),
+        }))
+    }
+
+    fn semantic_tokens(
+        &self,
+        DocumentParams { text_document }: DocumentParams,
+    ) -> Result<Value, ResponseError> {
+        let document = self.document(&text_document.uri)?;
+        let text = document.text.as_str();
+        let index = LineIndex::new(text);
+
+        let data = match self.document_top_match(document) {
+            Some(top_match) => {
+                let painted = paint(
+                    text.len(),
+                    &top_match.byte_ranges(),
+                    &FragSpecs::DEFAULT_PRIORITY,
+                );
+
+                encode_tokens(text, &index, &painted)
+            }
+            None => Vec::new(),
+        };
+
+        Ok(json!({ "data": data }))
+    }
+
+    fn code_action(
+        &self,
+        CodeActionParams {
+            text_document,
+            range,
+        }: CodeActionParams,
+    ) -> Result<Value, ResponseError> {
+        if range.start == range.end {
+            return Ok(json!([]));
+        }
+
+        Ok(json!([{
+            "title": "Show fragment tree",
+            "command": {
+                "title": "Show fragment tree",
+                "command": SHOW_FRAGMENT_TREE,
+                "arguments": [text_document.uri, range],
+            },
+        }]))
+    }
+
+    /// Shows the outline of the fragment tree of the selection, and returns it too.
+    fn execute_command(
+        &mut self,
+        ExecuteCommandParams { command, arguments }: ExecuteCommandParams,
+    ) -> Result<Value, ResponseError> {
+        if command != SHOW_FRAGMENT_TREE {
+            return Err(ResponseError::new(
+                rpc::INVALID_PARAMS,
+                format!("unknown command: {}", command),
+            ));
+        }
+
+        let (uri, range): (String, Range) = params(Value::Array(arguments))?;
+
+        let text = self.document(&uri)?.text.as_str();
+        let selection = LineIndex::new(text)
+            .byte_range(range)
+            .and_then(|range| text.get(range))
+            .ok_or_else(|| ResponseError::new(rpc::INVALID_PARAMS, "invalid range"))?;
+
+        let message = match self.top_match(selection) {
+            Some(TopMatch { matcher, tree, .. }) => {
+                format!(
+                    "Whole Match: {}\n{}",
+                    matcher_name(matcher),
+                    outline(selection, &tree, |_, snippet| snippet)
+                )
+            }
+            None => "The selection matches no syntax".to_string(),
+        };
+
+        self.outgoing.push(rpc::notification(
+            "window/showMessage",
+            // Info
+            json!({ "type": 3, "message": message }),
+        ));
+
+        Ok(Value::String(message))
+    }
+}
+
+/// Semantic tokens in the relative form of the spec. A token never spans lines.
+fn encode_tokens(text: &str, index: &LineIndex, painted: &[Option<FragSpecs>]) -> Vec<usize> {
+    let types = FragSpecs::value_variants();
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let frag = painted[start];
+        let mut end = start;
+        for (i, c) in text[start..].char_indices() {
+            let i = start + i;
+            if painted[i] != frag || c == '\n' || c == '\r' {
+                break;
+            }
+            end = i + c.len_utf8();
+        }
+
+        match frag {
+            Some(frag) if end > start => tokens.push((start..end, frag)),
+            _ => {}
+        }
+
+        // skip the line break, or the unpainted char
+        if end == start {
+            end += text[start..].chars().next().unwrap().len_utf8();
+        }
+        start = end;
+    }
+
+    let mut data = Vec::new();
+    let (mut prev_line, mut prev_char) = (0, 0);
+    for (range, frag) in tokens {
+        let start = index.position(range.start);
+        let end = index.position(range.end);
+
+        let delta_line = start.line - prev_line;
+        let delta_char = if delta_line == 0 {
+            start.character - prev_char
+        } else {
+            start.character
+        };
+
+        data.extend([
+            delta_line,
+            delta_char,
+            end.character - start.character,
+            types.iter().position(|f| *f == frag).unwrap(),
+            0,
+        ]);
+
+        (prev_line, prev_char) = (start.line, start.character);
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///a.rs";
+    const TEXT: &str = "fn main() {\n    let あ = foo(1, \"x\");\n}\n";
+
+    /// Runs a session as a client would, through the framing of `rpc` ,
+    /// and returns the messages sent back and the exit code.
+    fn session(messages: &[Value]) -> (Vec<Value>, Option<i32>) {
+        let mut input = Vec::new();
+        for message in messages {
+            rpc::write_message(&mut input, message).unwrap();
+        }
+        let mut input = Cursor::new(input);
+
+        let mut server = Server::new(Edition::default());
+        let mut output = Vec::new();
+
+        while let Some(message) = rpc::read_message(&mut input).unwrap() {
+            let (responses, flow) = server.handle(&message);
+            for response in &responses {
+                rpc::write_message(&mut output, response).unwrap();
+            }
+
+            if let Flow::Exit(code) = flow {
+                return (read_all(output), Some(code));
+            }
+        }
+
+        (read_all(output), None)
+    }
+
+    fn read_all(output: Vec<u8>) -> Vec<Value> {
+        let mut output = Cursor::new(output);
+        let mut res = Vec::new();
+
+        while let Some(message) = rpc::read_message(&mut output).unwrap() {
+            res.push(serde_json::from_str(&message).unwrap());
+        }
+
+        res
+    }
+
+    fn request(id: i64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn open() -> Value {
+        rpc::notification(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "rust", "version": 1, "text": TEXT } }),
+        )
+    }
+
+    #[test]
+    fn scripted_session() {
+        let range = json!({ "start": { "line": 1, "character": 13 }, "end": { "line": 1, "character": 23 } });
+
+        let (responses, code) = session(&[
+            request(
+                1,
+                "initialize",
+                json!({ "initializationOptions": { "edition": "2024" } }),
+            ),
+            rpc::notification("initialized", json!({})),
+            open(),
+            // `foo` , after a non-ASCII ident which is 1 in UTF-16
+            request(
+                2,
+                "textDocument/hover",
+                json!({ "textDocument": { "uri": URI }, "position": { "line": 1, "character": 13 } }),
+            ),
+            request(
+                3,
+                "textDocument/semanticTokens/full",
+                json!({ "textDocument": { "uri": URI } }),
+            ),
+            request(
+                4,
+                "textDocument/codeAction",
+                json!({ "textDocument": { "uri": URI }, "range": range, "context": { "diagnostics": [] } }),
+            ),
+            request(
+                5,
+                "workspace/executeCommand",
+                json!({ "command": SHOW_FRAGMENT_TREE, "arguments": [URI, range] }),
+            ),
+            request(6, "foo", json!({})),
+            request(7, "shutdown", Value::Null),
+            rpc::notification("exit", Value::Null),
+        ]);
+
+        assert_eq!(code, Some(0));
+
+        let [initialize, hover, tokens, action, command, message, unknown, shutdown] =
+            responses.try_into().unwrap();
+
+        assert_eq!(
+            initialize["result"]["capabilities"]["executeCommandProvider"]["commands"][0],
+            SHOW_FRAGMENT_TREE
+        );
+
+        assert_eq!(
+            hover["result"]["range"],
+            json!({ "start": { "line": 1, "character": 12 }, "end": { "line": 1, "character": 15 } })
+        );
+        let hover = hover["result"]["contents"]["value"].as_str().unwrap();
+        assert!(hover.starts_with("**Ident**"), "{}", hover);
+        assert!(hover.contains("`ident`"), "{}", hover);
+
+        // 5 integers per token
+        let data = tokens["result"]["data"].as_array().unwrap();
+        assert!(!data.is_empty() && data.len() % 5 == 0, "{:?}", data);
+
+        assert_eq!(
+            action["result"][0]["command"]["command"],
+            SHOW_FRAGMENT_TREE
+        );
+
+        let tree = command["result"].as_str().unwrap();
+        assert!(tree.starts_with("Whole Match: expr\n"), "{}", tree);
+        assert_eq!(message["method"], "window/showMessage");
+        assert_eq!(message["params"]["message"], tree);
+
+        assert_eq!(unknown["error"]["code"], rpc::METHOD_NOT_FOUND);
+        assert_eq!(shutdown["result"], Value::Null);
+    }
+
+    #[test]
+    fn exit_without_shutdown() {
+        let (responses, code) = session(&[open(), rpc::notification("exit", Value::Null)]);
+
+        assert!(responses.is_empty());
+        assert_eq!(code, Some(1));
+    }
+
+    #[test]
+    fn requests_after_shutdown_are_rejected() {
+        let (responses, code) = session(&[
+            request(1, "shutdown", Value::Null),
+            request(
+                2,
+                "textDocument/semanticTokens/full",
+                json!({ "textDocument": { "uri": URI } }),
+            ),
+        ]);
+
+        assert_eq!(code, None);
+        assert_eq!(responses[1]["error"]["code"], rpc::INVALID_REQUEST);
+    }
+
+    #[test]
+    fn a_change_replaces_the_classification() {
+        let hover = |id| {
+            request(
+                id,
+                "textDocument/hover",
+                json!({ "textDocument": { "uri": URI }, "position": { "line": 0, "character": 0 } }),
+            )
+        };
+        let change = rpc::notification(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": "x + 1" }],
+            }),
+        );
+
+        let (responses, _) = session(&[open(), hover(1), hover(2), change, hover(3)]);
+        let kind = |response: &Value| {
+            let value = response["result"]["contents"]["value"].as_str().unwrap();
+            value.lines().next().unwrap().to_string()
+        };
+
+        assert_eq!(kind(&responses[0]), "**ItemFn**");
+        assert_eq!(kind(&responses[2]), "**Ident**");
+        assert_eq!(responses[0]["result"], responses[1]["result"]);
+        assert_eq!(
+            responses[2]["result"]["range"],
+            json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 1 } })
+        );
+    }
+}
@@ -1,23 +1,46 @@
-use std::{collections::HashMap, ops::Range};
-
-use coloring_common::{Colored, FragSpecs};
-use coloring_macro::repeat_for_types;
-use html_escape::encode_text;
+use std::ops::Range;
+
+use coloring_common::mbe::{
+    ArmError, Binding, BoundFragment, ExpandedToken, Expansion, MacroMatch, MacroRules,
+    NamedBinding, TokenOrigin,
+};
+use coloring_common::{
+    classify, diagnostics, recovered_to_html_string, to_html_string, why_not, Classification,
+    Diagnostic, Edition, FragNode, FragRange, FragRangeMap, FragSpecs, MatchError, Offset, Piece,
+    RangeMap, Stats, TopMatch, WholeMatcher, WhyNot,
+};
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
 use wasm_bindgen::prelude::*;
 
 #[derive(Deserialize)]
 struct Input {
     code: String,
-    filters: Vec<Filter>,
+    filters: Vec<WholeMatcher>,
+    #[serde(default)]
+    edition: Edition,
+    /// Highlight what can be parsed when no filter matches, instead of listing the errors.
+    #[serde(default)]
+    recover: bool,
 }
 
 #[derive(Serialize)]
 struct Output {
-    hit_top_filter: Option<Filter>,
-    hit_filters: Vec<Filter>,
+    hit_top_filter: Option<WholeMatcher>,
+    hit_filters: Vec<WholeMatcher>,
     colored: String,
+    /// Sorted by the start position.
+    fragments: Vec<FragmentOutput>,
+    tree: Vec<FragNode>,
+    /// Where the failed filters failed.
+    diagnostics: Vec<Diagnostic>,
+    /// Why the failed filters failed, in words.
+    why_not: Vec<WhyNot>,
+}
+
+#[derive(Serialize)]
+struct FragmentOutput {
+    frag: FragSpecs,
+    range: FragRange,
 }
 
 #[wasm_bindgen]
@@ -29,214 +52,309 @@ pub fn colored(input: JsValue) -> Result<JsValue, JsValue> {
     Ok(serde_wasm_bindgen::to_value(&output)?)
 }
 
-struct TopFilter {
-    filter: Filter,
-    colored: String,
-}
-
-fn colored_inner(Input { code, filters }: Input) -> Output {
-    let mut errors = String::new();
-
-    let mut top_filter = None;
-    for &filter in filters.iter() {
-        match filter.try_parse(&code) {
-            Ok(ranges) => {
-                if top_filter.is_none() {
-                    top_filter = Some(TopFilter {
-                        filter,
-                        colored: to_html_string(&code, ranges),
-                    });
-                }
+fn colored_inner(
+    Input {
+        code,
+        filters,
+        edition,
+        recover,
+    }: Input,
+) -> Output {
+    let Classification {
+        top_match,
+        hit_matchers,
+        errors,
+    } = classify(&code, &filters, edition);
+
+    let diagnostics = diagnostics(&code, &errors);
+    let why_not = why_not(&code, &errors, edition);
+
+    match top_match {
+        Some(top_match) => {
+            let colored = to_html_string(&code, top_match.byte_ranges());
+
+            let TopMatch {
+                matcher,
+                ranges,
+                tree,
+            } = top_match;
+
+            Output {
+                hit_top_filter: Some(matcher),
+                hit_filters: hit_matchers,
+                colored,
+                fragments: fragment_outputs([ranges]),
+                tree,
+                diagnostics,
+                why_not,
             }
-            Err(e) => errors.push_str(&e),
         }
-    }
-
-    match top_filter {
-        Some(TopFilter { filter, colored }) => Output {
-            hit_top_filter: Some(filter),
-            hit_filters: filters
-                .into_iter()
-                .filter(|f| f.try_parse(&code).is_ok())
-                .collect(),
-            colored,
-        },
-        None => Output {
+        None if !recover => Output {
             hit_top_filter: None,
             hit_filters: vec![],
-            colored: errors,
+            colored: errors
+                .iter()
+                .map(|MatchError { matcher, error }| {
+                    format!("Failed to parse {}\n---\n{}", matcher, error)
+                })
+                .collect(),
+            fragments: vec![],
+            tree: vec![],
+            diagnostics,
+            why_not,
         },
+        None => {
+            // highlight what can be parsed and show the errors inline
+            let recovered = coloring_common::recover(&code, &filters, edition);
+            let colored = recovered_to_html_string(&code, &recovered);
+
+            let ranges = recovered
+                .pieces
+                .into_iter()
+                .filter_map(|piece| match piece {
+                    Piece::Parsed { ranges, .. } => Some(ranges),
+                    Piece::Failed { .. } => None,
+                });
+
+            Output {
+                hit_top_filter: None,
+                hit_filters: vec![],
+                colored,
+                fragments: fragment_outputs(ranges),
+                tree: vec![],
+                diagnostics,
+                why_not,
+            }
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
-#[serde(rename_all = "lowercase")]
-enum Filter {
-    File,
-    Item,
-    Block,
-    Stmt,
-    Expr,
-    #[serde(rename = "ty")]
-    Type,
-    Path,
-    #[serde(rename = "vis")]
-    Visibility,
-    Ident,
-    Lifetime,
-    #[serde(rename = "literal")]
-    Lit,
-    Meta,
-}
-
-impl Filter {
-    fn try_parse(self, content: &str) -> Result<HashMap<FragSpecs, Vec<Range<usize>>>, String> {
-        repeat_for_types!(for F in [
-            File,
-            Item,
-            Block,
-            Stmt,
-            Expr,
-            Type,
-            Path,
-            Visibility,
-            Ident,
-            Lifetime,
-            Lit,
-            Meta,
-        ] {
-            match self {
-                #(
-                    Filter::F => {
-                        let parsed = syn::parse_str::<syn::F>(content)
-                            .map_err(|e| format!("Failed to parse {}\n---\n{}", stringify!(F), e))?;
-
-                        let mut colored = Colored::<syn::F>::new(parsed);
-                        colored.visit();
-
-                        Ok(colored.ranges)
-                    },
-                )*
-            }
+fn fragment_outputs(ranges: impl IntoIterator<Item = FragRangeMap>) -> Vec<FragmentOutput> {
+    let mut fragments = ranges
+        .into_iter()
+        .flatten()
+        .flat_map(|(frag, ranges)| {
+            ranges
+                .into_iter()
+                .map(move |range| FragmentOutput { frag, range })
         })
-    }
+        .collect::<Vec<_>>();
+    fragments.sort_by_key(|f| (f.range.byte.start, f.range.byte.end, f.frag));
+
+    fragments
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-enum SpanTag {
-    Start(FragSpecs, usize),
-    End,
+#[derive(Serialize)]
+struct StatsOutput {
+    hit_top_filter: Option<WholeMatcher>,
+    /// Empty when no filter accepts the whole code.
+    stats: Stats,
 }
 
-impl PartialOrd for SpanTag {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+#[wasm_bindgen]
+pub fn stats(input: JsValue) -> Result<JsValue, JsValue> {
+    let Input {
+        code,
+        filters,
+        edition,
+        ..
+    } = serde_wasm_bindgen::from_value(input)?;
+
+    let Classification { top_match, .. } = classify(&code, &filters, edition);
+
+    let output = StatsOutput {
+        hit_top_filter: top_match.as_ref().map(|top_match| top_match.matcher),
+        stats: top_match.map_or_else(Stats::default, |top_match| {
+            coloring_common::stats(code.len(), &top_match.tree)
+        }),
+    };
+
+    // plain objects instead of `Map` for the maps
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    Ok(output.serialize(&serializer)?)
 }
 
-impl Ord for SpanTag {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // End < Start
-        // Start 2 < Start 1
-        match (self, other) {
-            (SpanTag::Start(_, _), SpanTag::End) => Ordering::Greater,
-            (SpanTag::End, SpanTag::Start(_, _)) => Ordering::Less,
-            (SpanTag::Start(_, len_self), SpanTag::Start(_, len_other)) => len_other.cmp(len_self),
-            (SpanTag::End, SpanTag::End) => Ordering::Equal,
-        }
-    }
+#[derive(Deserialize)]
+struct QueryInput {
+    code: String,
+    filters: Vec<WholeMatcher>,
+    #[serde(default)]
+    edition: Edition,
+    offset: Offset,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-enum SpanTagForRender {
-    Start(FragSpecs, Vec<FragSpecs>),
-    End,
+#[wasm_bindgen]
+pub fn fragments_at(input: JsValue) -> Result<JsValue, JsValue> {
+    let QueryInput {
+        code,
+        filters,
+        edition,
+        offset,
+    } = serde_wasm_bindgen::from_value(input)?;
+
+    let output = coloring_common::fragments_at(&code, offset, &filters, edition);
+
+    Ok(serde_wasm_bindgen::to_value(&output)?)
 }
 
-fn span_tag_map(
-    len: usize,
-    ranges: HashMap<FragSpecs, Vec<Range<usize>>>,
-) -> Vec<Vec<SpanTagForRender>> {
-    let mut tags_all: Vec<Vec<SpanTag>> = (0..len + 1).map(|_| Vec::new()).collect();
+#[derive(Deserialize)]
+struct MacroInput {
+    definition: String,
+    invocation: String,
+    #[serde(default)]
+    edition: Edition,
+}
 
-    for (frag, ranges) in ranges.into_iter() {
-        for Range { start, end } in ranges {
-            if end == 0 || end > len {
-                // invalid range
-                continue;
-            }
+#[derive(Serialize)]
+struct MacroOutput {
+    matched_arm: Option<usize>,
+    bindings: Vec<BindingOutput>,
+    errors: Vec<String>,
+    colored: String,
+    expansion: Option<ExpansionOutput>,
+}
+
+#[derive(Serialize)]
+struct ExpansionOutput {
+    code: String,
+    tokens: Vec<ExpandedTokenOutput>,
+}
 
-            tags_all[start].push(SpanTag::Start(frag, end - start));
-            tags_all[end].push(SpanTag::End);
+#[derive(Serialize)]
+struct ExpandedTokenOutput {
+    text: String,
+    origin: OriginOutput,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum OriginOutput {
+    Transcriber { range: Range<usize> },
+    Metavar { name: String, range: Range<usize> },
+}
+
+impl From<Expansion> for ExpansionOutput {
+    fn from(Expansion { tokens, annotated }: Expansion) -> Self {
+        Self {
+            code: tokens.to_string(),
+            tokens: annotated
+                .into_iter()
+                .map(|ExpandedToken { text, origin }| ExpandedTokenOutput {
+                    text,
+                    origin: match origin {
+                        TokenOrigin::Transcriber(range) => OriginOutput::Transcriber { range },
+                        TokenOrigin::MetaVar { name, byte_range } => OriginOutput::Metavar {
+                            name,
+                            range: byte_range,
+                        },
+                    },
+                })
+                .collect(),
         }
     }
+}
 
-    for tags in tags_all.iter_mut() {
-        tags.sort(); // </span> </span> <span class="..."> <span class="...">
-    }
+#[derive(Serialize)]
+struct BindingOutput {
+    name: String,
+    kind: String,
+    binding: BindingNode,
+}
 
-    let mut stack = Vec::new();
-    let mut tags_for_render_all: Vec<Vec<SpanTagForRender>> =
-        (0..len + 1).map(|_| Vec::new()).collect();
-    for (i, tags_for_render) in tags_for_render_all.iter_mut().enumerate() {
-        for tag in tags_all[i].iter() {
-            match tag {
-                SpanTag::End => {
-                    stack.pop();
-
-                    tags_for_render.push(SpanTagForRender::End);
-                }
-                SpanTag::Start(frag, _) => {
-                    stack.push(*frag);
-
-                    tags_for_render.push(SpanTagForRender::Start(*frag, stack.clone()));
-                }
-            }
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum BindingNode {
+    Single { range: Range<usize>, text: String },
+    Seq { items: Vec<BindingNode> },
+}
+
+impl BindingNode {
+    fn new(binding: &Binding, invocation: &str) -> Self {
+        match binding {
+            Binding::Single(BoundFragment { byte_range, .. }) => BindingNode::Single {
+                range: byte_range.clone(),
+                text: invocation[byte_range.clone()].to_string(),
+            },
+            Binding::Seq(seq) => BindingNode::Seq {
+                items: seq
+                    .iter()
+                    .map(|b| BindingNode::new(b, invocation))
+                    .collect(),
+            },
         }
     }
+}
 
-    tags_for_render_all
-}
-
-fn to_html_string(content: &str, ranges: HashMap<FragSpecs, Vec<Range<usize>>>) -> String {
-    let span_tag_map = span_tag_map(content.bytes().len(), ranges);
-
-    let mut res: String = content
-        .char_indices()
-        .map(|(i, c)| {
-            let mut res = String::new();
-
-            for tag in &span_tag_map[i] {
-                let tag = match tag {
-                    SpanTagForRender::End => "</span>".to_string(),
-                    SpanTagForRender::Start(ref frag, ref frags) => {
-                        let frags = frags
-                            .iter()
-                            .map(|f| f.to_string().to_ascii_lowercase())
-                            .collect::<Vec<_>>()
-                            .join(" ");
-
-                        format!(
-                            "<span data-frag=\"{}\" class=\"{}\">",
-                            frag.to_string().to_ascii_lowercase(),
-                            frags
-                        )
-                    }
-                };
-                res.push_str(&tag);
-            }
+#[wasm_bindgen]
+pub fn match_macro(input: JsValue) -> Result<JsValue, JsValue> {
+    let input = serde_wasm_bindgen::from_value(input)?;
 
-            res.push_str(&encode_text(&c.to_string()));
+    let output = match_macro_inner(input);
 
-            res
-        })
-        .collect();
+    Ok(serde_wasm_bindgen::to_value(&output)?)
+}
 
-    for tag in span_tag_map.last().unwrap() {
-        if let &SpanTagForRender::End = tag {
-            res.push_str("</span>");
+fn match_macro_inner(
+    MacroInput {
+        definition,
+        invocation,
+        edition,
+    }: MacroInput,
+) -> MacroOutput {
+    let failed = |errors| MacroOutput {
+        matched_arm: None,
+        bindings: vec![],
+        errors,
+        colored: to_html_string(&invocation, RangeMap::new()),
+        expansion: None,
+    };
+
+    let rules = match MacroRules::parse(&definition, edition) {
+        Ok(rules) => rules,
+        Err(e) => return failed(vec![e.to_string()]),
+    };
+
+    let MacroMatch { matched, errors } = match rules.match_invocation(&invocation) {
+        Ok(res) => res,
+        Err(e) => return failed(vec![e.to_string()]),
+    };
+
+    let mut errors = errors
+        .into_iter()
+        .map(|ArmError { arm, error }| format!("Arm #{}: {}", arm, error))
+        .collect::<Vec<_>>();
+
+    let Some(arm_match) = matched else {
+        return failed(errors);
+    };
+
+    let expansion = match rules.transcribe(&arm_match) {
+        Ok(expansion) => Some(expansion.into()),
+        Err(e) => {
+            errors.push(format!("Transcription: {}", e));
+            None
         }
+    };
+
+    MacroOutput {
+        matched_arm: Some(arm_match.arm),
+        bindings: arm_match
+            .bindings
+            .iter()
+            .map(
+                |NamedBinding {
+                     name,
+                     kind,
+                     binding,
+                 }| BindingOutput {
+                    name: name.clone(),
+                    kind: kind.to_string(),
+                    binding: BindingNode::new(binding, &invocation),
+                },
+            )
+            .collect(),
+        errors,
+        colored: to_html_string(&invocation, arm_match.ranges()),
+        expansion,
     }
-
-    res
 }
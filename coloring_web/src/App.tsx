@@ -34,6 +34,7 @@ function App() {
     code: input,
     filters: filterList
       .flatMap(item => item.active ? [item.name] : []),
+    recover: true,
   });
 
   return (
@@ -24,5 +24,7 @@ export const initFilterArray = (): Filter[] => {
     filterNew("lifetime"),
     filterNew("literal"),
     filterNew("meta"),
+    filterNew("pat"),
+    filterNew("pat_param"),
   ];
 };
\ No newline at end of file
@@ -79,10 +79,12 @@ export const initHighlightTargetArray = (is_dark_mode: boolean): HighlightTarget
     highlightTargetNew("ident", "orange", !is_dark_mode),
     highlightTargetNew("path", "lime", !is_dark_mode),
     highlightTargetNew("pat", "lightgreen", !is_dark_mode),
+    highlightTargetNew("pat_param", "mediumseagreen", !is_dark_mode),
     highlightTargetNew("ty", "green", !is_dark_mode),
     highlightTargetNew("lifetime", "aquamarine", !is_dark_mode),
     highlightTargetNew("vis", "pink", !is_dark_mode),
     highlightTargetNew("expr", "cyan", !is_dark_mode),
+    highlightTargetNew("expr_2021", "darkturquoise", !is_dark_mode),
     highlightTargetNew("stmt", "lightskyblue", !is_dark_mode),
     highlightTargetNew("meta", "violet", !is_dark_mode),
     highlightTargetNew("block", block_color, false),